@@ -1,7 +1,16 @@
+use std::fmt;
+use crate::bigint::BigInt;
+use crate::currency::Currency;
+use crate::decimal::Decimal;
+use crate::rational::Rational;
+use crate::span::Span;
+use crate::units::Quantity;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum UnaryOperator {
   Negative,
   Not,
+  BitNot,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -11,6 +20,7 @@ pub enum BinaryOperator {
   Multiply,
   Divide,
   Modulo,
+  Power,
   Equal,
   NotEqual,
   LessThan,
@@ -19,21 +29,142 @@ pub enum BinaryOperator {
   GreaterThanOrEqual,
   And,
   Or,
+  BitAnd,
+  BitOr,
+  BitXor,
+  ShiftLeft,
+  ShiftRight,
+  ShiftRightUnsigned,
+}
+
+impl fmt::Display for UnaryOperator {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let symbol = match self {
+      UnaryOperator::Negative => "-",
+      UnaryOperator::Not => "!",
+      UnaryOperator::BitNot => "~",
+    };
+
+    write!(f, "{}", symbol)
+  }
+}
+
+impl fmt::Display for BinaryOperator {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let symbol = match self {
+      BinaryOperator::Add => "+",
+      BinaryOperator::Subtract => "-",
+      BinaryOperator::Multiply => "*",
+      BinaryOperator::Divide => "/",
+      BinaryOperator::Modulo => "%",
+      BinaryOperator::Power => "^",
+      BinaryOperator::Equal => "==",
+      BinaryOperator::NotEqual => "!=",
+      BinaryOperator::LessThan => "<",
+      BinaryOperator::GreaterThan => ">",
+      BinaryOperator::LessThanOrEqual => "<=",
+      BinaryOperator::GreaterThanOrEqual => ">=",
+      BinaryOperator::And => "&&",
+      BinaryOperator::Or => "||",
+      BinaryOperator::BitAnd => "&",
+      BinaryOperator::BitOr => "|",
+      BinaryOperator::BitXor => "xor",
+      BinaryOperator::ShiftLeft => "<<",
+      BinaryOperator::ShiftRight => ">>",
+      BinaryOperator::ShiftRightUnsigned => ">>>",
+    };
+
+    write!(f, "{}", symbol)
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SeriesOp {
+  Sum,
+  Product,
 }
 
 #[derive(Debug, Clone, PartialEq)]
-pub enum Expr {
+pub enum ExprKind {
   Number(f64),
+  Integer(i64),
+  BigInt(BigInt),
+  Rational(Rational),
+  Decimal(Decimal),
+  /// The imaginary part of an imaginary literal such as `2.5i`.
+  Imaginary(f64),
   Boolean(bool),
+  String(String),
   Identifier(String),
   UnaryOp(UnaryOperator, Box<Expr>),
   BinaryOp(Box<Expr>, BinaryOperator, Box<Expr>),
-  Call(Box<Expr>, Box<Vec<Expr>>),
+  Call(Box<Expr>, Vec<Expr>),
+  List(Vec<Expr>),
+  Index(Box<Expr>, Box<Expr>),
+  /// `value[start:end]`, where either bound may be left out.
+  Slice(Box<Expr>, Option<Box<Expr>>, Option<Box<Expr>>),
+  /// One of a unit product, written after a number as in `5 km`.
+  Unit(Quantity),
+  /// `value to unit` or `value in unit`.
+  Convert(Box<Expr>, Quantity),
+  /// A currency code written after a number, as in `5 USD`.
+  Currency(Currency),
+  /// `value to EUR` or `value in EUR`.
+  ConvertCurrency(Box<Expr>, Currency),
+  If(Box<Expr>, Box<Expr>, Box<Expr>),
+  /// `start..end`, or `start..=end` when inclusive, optionally followed by
+  /// `step step`.
+  Range(Box<Expr>, Box<Expr>, Option<Box<Expr>>, bool),
+  /// `sum(k in iterable, body)` or `product(k in iterable, body)`.
+  Series(SeriesOp, String, Box<Expr>, Box<Expr>),
+  /// `let x = 1, y = x + 1 in body`, each binding seeing those before it.
+  Let(Vec<(String, Expr)>, Box<Expr>),
+  /// `x => body` or `(x, y) => body`.
+  Lambda(Vec<String>, Box<Expr>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Expr {
+  pub kind: ExprKind,
+  pub span: Span,
+}
+
+impl Expr {
+  pub fn new(kind: ExprKind, span: Span) -> Self {
+    Self {
+      kind,
+      span,
+    }
+  }
+}
+
+impl From<ExprKind> for Expr {
+  fn from(kind: ExprKind) -> Self {
+    Expr::new(kind, Span::default())
+  }
+}
+
+/// Expressions compare by shape only, so the same tree parsed from
+/// differently spaced input is still equal.
+impl PartialEq for Expr {
+  fn eq(&self, other: &Self) -> bool {
+    self.kind == other.kind
+  }
 }
 
 #[derive(Debug, PartialEq)]
 pub enum Stmt {
   Expr(Expr),
+  Assign(String, Expr),
+  /// `name(params) = body`.
+  Function(String, Vec<String>, Expr),
+  /// `{ stmt; stmt }`, with the value of its last statement.
+  Block(Vec<Stmt>),
+  /// `while condition { body }`, which leaves no value.
+  While(Expr, Vec<Stmt>),
+  /// `for name in iterable { body }` over a range, list, matrix rows or
+  /// string characters, which leaves no value.
+  For(String, Expr, Vec<Stmt>),
 }
 
 pub type Program = Vec<Stmt>;
\ No newline at end of file
@@ -0,0 +1,493 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+/// An arbitrary-precision signed integer stored as little-endian base 2^32
+/// limbs. Zero has no limbs and is never negative.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BigInt {
+  negative: bool,
+  limbs: Vec<u32>,
+}
+
+fn trim(limbs: &mut Vec<u32>) {
+  while limbs.last() == Some(&0) {
+    limbs.pop();
+  }
+}
+
+fn cmp_mag(a: &[u32], b: &[u32]) -> Ordering {
+  a.len().cmp(&b.len()).then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+  let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+  let mut result = Vec::with_capacity(long.len() + 1);
+  let mut carry = 0u64;
+
+  for (i, limb) in long.iter().enumerate() {
+    let sum = *limb as u64 + *short.get(i).unwrap_or(&0) as u64 + carry;
+    result.push(sum as u32);
+    carry = sum >> 32;
+  }
+
+  if carry > 0 {
+    result.push(carry as u32);
+  }
+
+  result
+}
+
+/// Subtracts magnitudes, requiring `a >= b`.
+fn sub_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+  let mut result = Vec::with_capacity(a.len());
+  let mut borrow = 0i64;
+
+  for (i, limb) in a.iter().enumerate() {
+    let diff = *limb as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+    result.push(diff as u32);
+    borrow = if diff < 0 { 1 } else { 0 };
+  }
+
+  trim(&mut result);
+  result
+}
+
+fn mul_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+  if a.is_empty() || b.is_empty() {
+    return Vec::new();
+  }
+
+  let mut result = vec![0u32; a.len() + b.len()];
+
+  for (i, x) in a.iter().enumerate() {
+    let mut carry = 0u64;
+
+    for (j, y) in b.iter().enumerate() {
+      let product = *x as u64 * *y as u64 + result[i + j] as u64 + carry;
+      result[i + j] = product as u32;
+      carry = product >> 32;
+    }
+
+    result[i + b.len()] = carry as u32;
+  }
+
+  trim(&mut result);
+  result
+}
+
+fn divmod_small(a: &[u32], divisor: u32) -> (Vec<u32>, u32) {
+  let mut quotient = vec![0u32; a.len()];
+  let mut remainder = 0u64;
+
+  for i in (0..a.len()).rev() {
+    let current = (remainder << 32) | a[i] as u64;
+    quotient[i] = (current / divisor as u64) as u32;
+    remainder = current % divisor as u64;
+  }
+
+  trim(&mut quotient);
+  (quotient, remainder as u32)
+}
+
+fn shl_bits(a: &[u32], shift: u32) -> Vec<u32> {
+  let mut result = Vec::with_capacity(a.len() + 1);
+  let mut carry = 0u32;
+
+  for limb in a {
+    result.push((limb << shift) | carry);
+    carry = if shift == 0 { 0 } else { limb >> (32 - shift) };
+  }
+
+  result.push(carry);
+  result
+}
+
+/// Long division of magnitudes (Knuth, TAOCP vol. 2, algorithm D).
+fn divmod_mag(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+  if cmp_mag(a, b) == Ordering::Less {
+    return (Vec::new(), a.to_vec());
+  }
+
+  if b.len() == 1 {
+    let (quotient, remainder) = divmod_small(a, b[0]);
+    let mut remainder = vec![remainder];
+    trim(&mut remainder);
+    return (quotient, remainder);
+  }
+
+  let shift = b[b.len() - 1].leading_zeros();
+  let mut v = shl_bits(b, shift);
+  v.pop();
+  let mut u = shl_bits(a, shift);
+
+  let n = v.len();
+  let m = u.len() - n;
+  let mut quotient = vec![0u32; m];
+  let base = 1u64 << 32;
+
+  for j in (0..m).rev() {
+    let numerator = ((u[j + n] as u64) << 32) | u[j + n - 1] as u64;
+    let mut qhat = numerator / v[n - 1] as u64;
+    let mut rhat = numerator % v[n - 1] as u64;
+
+    while qhat >= base || qhat * v[n - 2] as u64 > ((rhat << 32) | u[j + n - 2] as u64) {
+      qhat -= 1;
+      rhat += v[n - 1] as u64;
+
+      if rhat >= base {
+        break;
+      }
+    }
+
+    let mut borrow = 0i64;
+    let mut carry = 0u64;
+
+    for i in 0..n {
+      let product = qhat * v[i] as u64 + carry;
+      carry = product >> 32;
+      let diff = u[i + j] as i64 - borrow - (product & 0xffff_ffff) as i64;
+      u[i + j] = diff as u32;
+      borrow = if diff < 0 { 1 } else { 0 };
+    }
+
+    let diff = u[j + n] as i64 - borrow - carry as i64;
+    u[j + n] = diff as u32;
+
+    if diff < 0 {
+      qhat -= 1;
+      let mut carry = 0u64;
+
+      for i in 0..n {
+        let sum = u[i + j] as u64 + v[i] as u64 + carry;
+        u[i + j] = sum as u32;
+        carry = sum >> 32;
+      }
+
+      u[j + n] = u[j + n].wrapping_add(carry as u32);
+    }
+
+    quotient[j] = qhat as u32;
+  }
+
+  u.truncate(n);
+
+  let mut remainder: Vec<u32> = (0..n).map(|i| {
+    let high = if shift == 0 || i + 1 == n { 0 } else { u[i + 1] << (32 - shift) };
+    (u[i] >> shift) | high
+  }).collect();
+
+  trim(&mut quotient);
+  trim(&mut remainder);
+  (quotient, remainder)
+}
+
+impl BigInt {
+  fn from_parts(negative: bool, mut limbs: Vec<u32>) -> Self {
+    trim(&mut limbs);
+
+    Self {
+      negative: negative && !limbs.is_empty(),
+      limbs,
+    }
+  }
+
+  pub fn zero() -> Self {
+    BigInt::from_parts(false, Vec::new())
+  }
+
+  pub fn from_i64(n: i64) -> Self {
+    BigInt::from_i128(n as i128)
+  }
+
+  pub fn from_i128(n: i128) -> Self {
+    let magnitude = n.unsigned_abs();
+    let limbs = (0..4).map(|i| (magnitude >> (32 * i)) as u32).collect();
+    BigInt::from_parts(n < 0, limbs)
+  }
+
+  /// Parses an unsigned decimal literal such as `1.5e30`, if its value is a
+  /// whole number of at most `max_digits` digits.
+  pub fn from_decimal_str(literal: &str, max_digits: usize) -> Option<Self> {
+    let (mantissa, exponent) = match literal.find(['e', 'E']) {
+      Some(index) => (&literal[..index], literal[index + 1..].parse::<i64>().ok()?),
+      None => (literal, 0),
+    };
+
+    let (whole, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let digits = format!("{}{}", whole, fraction);
+    let digits = digits.trim_start_matches('0');
+    let scale = exponent.checked_sub(fraction.len() as i64)?;
+
+    if digits.is_empty() {
+      return Some(BigInt::zero());
+    }
+
+    let digits = match usize::try_from(scale) {
+      Ok(zeros) if digits.len().saturating_add(zeros) <= max_digits => format!("{}{}", digits, "0".repeat(zeros)),
+      Ok(_) => return None,
+      Err(_) => {
+        // Only zeros may follow the decimal point.
+        let point = digits.len().checked_sub(usize::try_from(scale.unsigned_abs()).ok()?)?;
+
+        if point > max_digits || !digits[point..].bytes().all(|digit| digit == b'0') {
+          return None;
+        }
+
+        digits[..point].to_string()
+      }
+    };
+
+    BigInt::parse(&digits, 10)
+  }
+
+  /// Parses unsigned digits in the given radix, ignoring `_` separators.
+  pub fn parse(digits: &str, radix: u32) -> Option<Self> {
+    let mut limbs: Vec<u32> = Vec::new();
+    let mut seen = false;
+
+    for ch in digits.chars().filter(|ch| *ch != '_') {
+      let digit = ch.to_digit(radix)?;
+      let mut carry = digit as u64;
+
+      for limb in limbs.iter_mut() {
+        let value = *limb as u64 * radix as u64 + carry;
+        *limb = value as u32;
+        carry = value >> 32;
+      }
+
+      if carry > 0 {
+        limbs.push(carry as u32);
+      }
+
+      seen = true;
+    }
+
+    if !seen {
+      return None;
+    }
+
+    Some(BigInt::from_parts(false, limbs))
+  }
+
+  pub fn is_zero(&self) -> bool {
+    self.limbs.is_empty()
+  }
+
+  pub fn is_negative(&self) -> bool {
+    self.negative
+  }
+
+  pub fn signum(&self) -> i64 {
+    match (self.is_zero(), self.negative) {
+      (true, _) => 0,
+      (false, true) => -1,
+      (false, false) => 1,
+    }
+  }
+
+  pub fn bits(&self) -> u64 {
+    match self.limbs.last() {
+      Some(top) => self.limbs.len() as u64 * 32 - top.leading_zeros() as u64,
+      None => 0,
+    }
+  }
+
+  pub fn to_i64(&self) -> Option<i64> {
+    i64::try_from(self.to_i128()?).ok()
+  }
+
+  pub fn to_i128(&self) -> Option<i128> {
+    if self.limbs.len() > 4 {
+      return None;
+    }
+
+    let magnitude = self.limbs.iter().rev().fold(0u128, |acc, limb| (acc << 32) | *limb as u128);
+
+    if self.negative {
+      0i128.checked_sub_unsigned(magnitude)
+    } else {
+      i128::try_from(magnitude).ok()
+    }
+  }
+
+  pub fn to_f64(&self) -> f64 {
+    let magnitude = self.limbs.iter().rev().fold(0.0, |acc, limb| acc * 4294967296.0 + *limb as f64);
+    if self.negative { -magnitude } else { magnitude }
+  }
+
+  pub fn neg(&self) -> Self {
+    BigInt::from_parts(!self.negative, self.limbs.clone())
+  }
+
+  pub fn abs(&self) -> Self {
+    BigInt::from_parts(false, self.limbs.clone())
+  }
+
+  pub fn add(&self, other: &Self) -> Self {
+    if self.negative == other.negative {
+      return BigInt::from_parts(self.negative, add_mag(&self.limbs, &other.limbs));
+    }
+
+    match cmp_mag(&self.limbs, &other.limbs) {
+      Ordering::Less => BigInt::from_parts(other.negative, sub_mag(&other.limbs, &self.limbs)),
+      _ => BigInt::from_parts(self.negative, sub_mag(&self.limbs, &other.limbs)),
+    }
+  }
+
+  pub fn sub(&self, other: &Self) -> Self {
+    self.add(&other.neg())
+  }
+
+  pub fn mul(&self, other: &Self) -> Self {
+    BigInt::from_parts(self.negative != other.negative, mul_mag(&self.limbs, &other.limbs))
+  }
+
+  /// Truncating division, so the remainder takes the sign of the dividend
+  /// like `%` on `i64`. Returns `None` when dividing by zero.
+  pub fn divmod(&self, other: &Self) -> Option<(Self, Self)> {
+    if other.is_zero() {
+      return None;
+    }
+
+    let (quotient, remainder) = divmod_mag(&self.limbs, &other.limbs);
+
+    Some((
+      BigInt::from_parts(self.negative != other.negative, quotient),
+      BigInt::from_parts(self.negative, remainder),
+    ))
+  }
+
+  pub fn pow(&self, mut exp: u32) -> Self {
+    let mut base = self.clone();
+    let mut result = BigInt::from_i64(1);
+
+    while exp > 0 {
+      if exp & 1 == 1 {
+        result = result.mul(&base);
+      }
+
+      exp >>= 1;
+
+      if exp > 0 {
+        base = base.mul(&base);
+      }
+    }
+
+    result
+  }
+
+  pub fn gcd(&self, other: &Self) -> Self {
+    let (mut a, mut b) = (self.abs(), other.abs());
+
+    while !b.is_zero() {
+      let (_, remainder) = a.divmod(&b).expect("divisor is non-zero");
+      (a, b) = (b, remainder);
+    }
+
+    a
+  }
+}
+
+impl PartialOrd for BigInt {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Ord for BigInt {
+  fn cmp(&self, other: &Self) -> Ordering {
+    match (self.negative, other.negative) {
+      (false, true) => Ordering::Greater,
+      (true, false) => Ordering::Less,
+      (false, false) => cmp_mag(&self.limbs, &other.limbs),
+      (true, true) => cmp_mag(&other.limbs, &self.limbs),
+    }
+  }
+}
+
+impl fmt::Display for BigInt {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    if self.is_zero() {
+      return write!(f, "0");
+    }
+
+    let mut chunks = Vec::new();
+    let mut limbs = self.limbs.clone();
+
+    while !limbs.is_empty() {
+      let (quotient, remainder) = divmod_small(&limbs, 1_000_000_000);
+      chunks.push(remainder);
+      limbs = quotient;
+    }
+
+    if self.negative {
+      write!(f, "-")?;
+    }
+
+    write!(f, "{}", chunks.pop().unwrap_or(0))?;
+
+    for chunk in chunks.iter().rev() {
+      write!(f, "{:09}", chunk)?;
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::bigint::BigInt;
+
+  #[test]
+  fn decimal_literals() {
+    let parse = |literal| BigInt::from_decimal_str(literal, 30).map(|value| value.to_string());
+
+    assert_eq!(parse("1.5e20").as_deref(), Some("150000000000000000000"));
+    assert_eq!(parse("250e-1").as_deref(), Some("25"));
+    assert_eq!(parse("0.0e5").as_deref(), Some("0"));
+    assert_eq!(parse("1e30"), None);
+    assert_eq!(parse("2.5"), None);
+    assert_eq!(parse("1e-9223372036854775808"), None);
+  }
+
+  #[test]
+  fn bigint() {
+    let values: Vec<i128> = vec![
+      0, 1, -1, 7, -13, 4294967295, 4294967296, -18446744073709551616,
+      123456789012345678901234567890, -98765432109876543210987654321,
+      170141183460469231731687303715884105727 / 3,
+    ];
+
+    for a in &values {
+      for b in &values {
+        let (x, y) = (BigInt::from_i128(*a), BigInt::from_i128(*b));
+
+        if let Some(sum) = a.checked_add(*b) {
+          assert_eq!(x.add(&y), BigInt::from_i128(sum), "{} + {}", a, b);
+          assert_eq!(x.sub(&y), BigInt::from_i128(a - b), "{} - {}", a, b);
+        }
+
+        if let Some(product) = a.checked_mul(*b) {
+          assert_eq!(x.mul(&y), BigInt::from_i128(product), "{} * {}", a, b);
+        }
+
+        if *b != 0 {
+          let (quotient, remainder) = x.divmod(&y).unwrap();
+          assert_eq!(quotient, BigInt::from_i128(a / b), "{} / {}", a, b);
+          assert_eq!(remainder, BigInt::from_i128(a % b), "{} % {}", a, b);
+        }
+
+        assert_eq!(x.cmp(&y), a.cmp(b));
+      }
+    }
+
+    let big = BigInt::from_i64(2).pow(200);
+    assert_eq!(big.to_string(), "1606938044258990275541962092341162602522202993782792835301376");
+    assert_eq!(BigInt::parse("1606938044258990275541962092341162602522202993782792835301376", 10), Some(big.clone()));
+    assert_eq!(big.divmod(&BigInt::from_i64(3).pow(50)).unwrap().0.to_string(), "2238393297946874000179418290327143433");
+    assert_eq!(big.gcd(&BigInt::from_i64(96)), BigInt::from_i64(32));
+    assert_eq!(BigInt::parse("ff_ff", 16), Some(BigInt::from_i64(65535)));
+    assert_eq!(BigInt::from_i64(i64::MIN).to_i64(), Some(i64::MIN));
+    assert_eq!(BigInt::from_i128(i64::MAX as i128 + 1).to_i64(), None);
+  }
+}
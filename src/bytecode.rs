@@ -1,17 +1,24 @@
 use crate::opcode::Opcode;
 use crate::object::Object;
+use crate::span::Span;
 
 #[derive(Debug, Clone)]
 pub struct Bytecode {
   pub codes: Vec<Opcode>,
+  /// Source span of each opcode, indexed in parallel with `codes`.
+  pub spans: Vec<Span>,
   pub constants: Vec<Object>,
+  /// Global variable names referenced by `GetGlobal` and `SetGlobal`.
+  pub names: Vec<String>,
 }
 
 impl Bytecode {
   pub fn new() -> Self {
     Self {
       codes: Vec::new(),
+      spans: Vec::new(),
       constants: Vec::new(),
+      names: Vec::new(),
     }
   }
 }
\ No newline at end of file
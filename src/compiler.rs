@@ -1,29 +1,70 @@
 use std::rc::Rc;
-use crate::ast::{BinaryOperator, Expr, Program, Stmt};
+use crate::ast::{BinaryOperator, Expr, ExprKind, Program, SeriesOp, Stmt, UnaryOperator};
 use crate::bytecode::Bytecode;
-use crate::object::Object;
+use crate::complex::Complex;
+use crate::currency::Money;
+use crate::error::{Error, Result};
+use crate::number;
+use crate::object::{Capture, Function, Object};
 use crate::opcode::Opcode;
+use crate::span::Span;
 
 #[derive(Debug)]
 pub struct Compiler {
   bytecode: Bytecode,
+  /// Variables of the function being compiled with their stack slots,
+  /// innermost last.
+  locals: Vec<(String, usize)>,
+  /// How many values the code so far leaves above the frame's first slot.
+  depth: usize,
+  /// Variables of enclosing functions used by this one, by upvalue index.
+  captures: Vec<Capture>,
+  enclosing: Option<Box<Compiler>>,
+}
+
+impl Default for Compiler {
+  fn default() -> Self {
+    Self::new()
+  }
 }
 
 impl Compiler {
   pub fn new() -> Self {
     Self {
       bytecode: Bytecode::new(),
+      locals: Vec::new(),
+      depth: 0,
+      captures: Vec::new(),
+      enclosing: None,
     }
   }
 
-  fn emit(&mut self, opcode: Opcode) -> usize {
+  fn emit(&mut self, opcode: Opcode, span: Span) -> usize {
+    self.depth = self.depth.saturating_add_signed(opcode.stack_effect());
     self.bytecode.codes.push(opcode);
+    self.bytecode.spans.push(span);
     self.bytecode.codes.len() - 1
   }
 
-  fn emit_bytes(&mut self, a: Opcode, b: Opcode) {
-    self.emit(a);
-    self.emit(b);
+  fn emit_bytes(&mut self, a: Opcode, b: Opcode, span: Span) {
+    self.emit(a, span);
+    self.emit(b, span);
+  }
+
+  fn emit_jump(&mut self, opcode: Opcode, span: Span) -> usize {
+    self.emit(opcode, span)
+  }
+
+  fn patch_jump(&mut self, index: usize) {
+    let target = self.bytecode.codes.len();
+
+    self.bytecode.codes[index] = match self.bytecode.codes[index] {
+      Opcode::Jump(_) => Opcode::Jump(target),
+      Opcode::JumpIfFalse(_) => Opcode::JumpIfFalse(target),
+      Opcode::JumpIfTrue(_) => Opcode::JumpIfTrue(target),
+      Opcode::IterNext(slot, _) => Opcode::IterNext(slot, target),
+      opcode => unreachable!("cannot patch {:?}", opcode),
+    };
   }
 
   fn add_constant(&mut self, constant: Object) -> usize {
@@ -31,93 +72,455 @@ impl Compiler {
     self.bytecode.constants.len() - 1
   }
 
-  fn compile_expr(&mut self, expr: &Expr) {
-    match expr {
-      Expr::Boolean(b) => {
+  fn add_name(&mut self, name: &str) -> usize {
+    if let Some(index) = self.bytecode.names.iter().position(|n| n == name) {
+      return index;
+    }
+
+    self.bytecode.names.push(name.to_string());
+    self.bytecode.names.len() - 1
+  }
+
+  fn resolve_local(&self, name: &str) -> Option<usize> {
+    self.locals.iter().rev().find(|(local, _)| local == name).map(|(_, slot)| *slot)
+  }
+
+  /// Finds `name` among the variables of the enclosing functions, capturing
+  /// it through each function in between.
+  fn resolve_upvalue(&mut self, name: &str) -> Option<usize> {
+    let enclosing = self.enclosing.as_mut()?;
+
+    let capture = match enclosing.resolve_local(name) {
+      Some(slot) => Capture::Local(slot),
+      None => Capture::Upvalue(enclosing.resolve_upvalue(name)?),
+    };
+
+    if let Some(index) = self.captures.iter().position(|c| *c == capture) {
+      return Some(index);
+    }
+
+    self.captures.push(capture);
+    Some(self.captures.len() - 1)
+  }
+
+  /// Compiles `body` into its own chunk and pushes the resulting function,
+  /// as a closure when it captures variables of this one.
+  fn compile_function(&mut self, name: &str, params: &[String], body: &Expr, span: Span) -> Result<()> {
+    let enclosing = std::mem::take(self);
+
+    self.enclosing = Some(Box::new(enclosing));
+    self.locals = params.iter().cloned().zip(0..).collect();
+    self.depth = params.len();
+
+    let result = self.compile_expr(body);
+    self.emit(Opcode::Return, body.span);
+
+    let enclosing = self.enclosing.take().expect("no enclosing compiler");
+    let compiler = std::mem::replace(self, *enclosing);
+
+    result?;
+
+    let function = Function {
+      name: name.to_string(),
+      params: params.to_vec(),
+      chunk: compiler.bytecode,
+      captures: compiler.captures,
+    };
+
+    let has_captures = !function.captures.is_empty();
+    let constant = self.add_constant(Object::Function(Rc::new(function)));
+
+    if has_captures {
+      self.emit(Opcode::Closure(constant), span);
+    } else {
+      self.emit(Opcode::Constant(constant), span);
+    }
+
+    Ok(())
+  }
+
+  fn compile_expr(&mut self, expr: &Expr) -> Result<()> {
+    let span = expr.span;
+
+    match &expr.kind {
+      ExprKind::Boolean(b) => {
         let constant = self.add_constant(Object::Boolean(*b));
-        self.emit(Opcode::Constant(constant));
+        self.emit(Opcode::Constant(constant), span);
+      }
+      ExprKind::String(s) => {
+        let constant = self.add_constant(Object::String(Rc::from(s.as_str())));
+        self.emit(Opcode::Constant(constant), span);
       }
-      Expr::Number(n) => {
+      ExprKind::Number(n) => {
         let constant = self.add_constant(Object::Number(*n));
-        self.emit(Opcode::Constant(constant));
+        self.emit(Opcode::Constant(constant), span);
+      }
+      ExprKind::Integer(n) => {
+        let constant = self.add_constant(Object::Integer(*n));
+        self.emit(Opcode::Constant(constant), span);
+      }
+      ExprKind::BigInt(n) => {
+        let constant = self.add_constant(Object::BigInt(Rc::new(n.clone())));
+        self.emit(Opcode::Constant(constant), span);
+      }
+      ExprKind::Decimal(d) => {
+        let constant = self.add_constant(Object::Decimal(*d));
+        self.emit(Opcode::Constant(constant), span);
+      }
+      ExprKind::Imaginary(n) => {
+        let constant = self.add_constant(number::from_complex(Complex::new(0.0, *n)));
+        self.emit(Opcode::Constant(constant), span);
+      }
+      ExprKind::Unit(unit) => {
+        let constant = self.add_constant(Object::Quantity(Rc::new(unit.clone())));
+        self.emit(Opcode::Constant(constant), span);
+      }
+      ExprKind::Convert(value, unit) => {
+        self.compile_expr(value)?;
+
+        let constant = self.add_constant(Object::Quantity(Rc::new(unit.clone())));
+        self.emit(Opcode::Convert(constant), span);
+      }
+      ExprKind::Currency(currency) => {
+        let constant = self.add_constant(Object::Money(Rc::new(Money::new(Object::Integer(1), *currency))));
+        self.emit(Opcode::Constant(constant), span);
       }
-      Expr::Identifier(name) => {
+      ExprKind::ConvertCurrency(value, currency) => {
+        self.compile_expr(value)?;
 
+        let constant = self.add_constant(Object::Money(Rc::new(Money::new(Object::Integer(1), *currency))));
+        self.emit(Opcode::Convert(constant), span);
+      }
+      ExprKind::Rational(r) => {
+        let constant = self.add_constant(Object::Rational(*r));
+        self.emit(Opcode::Constant(constant), span);
+      }
+      ExprKind::Identifier(name) => {
+        if let Some(slot) = self.resolve_local(name) {
+          self.emit(Opcode::GetLocal(slot), span);
+        } else if let Some(index) = self.resolve_upvalue(name) {
+          self.emit(Opcode::GetUpvalue(index), span);
+        } else {
+          let name = self.add_name(name);
+          self.emit(Opcode::GetGlobal(name), span);
+        }
       }
-      Expr::BinaryOp(left, op, right) => {
-        self.compile_expr(left);
-        self.compile_expr(right);
+      ExprKind::UnaryOp(op, operand) => {
+        self.compile_expr(operand)?;
+
+        match op {
+          UnaryOperator::Negative => {
+            self.emit(Opcode::Negate, span);
+          },
+          UnaryOperator::Not => {
+            self.emit(Opcode::Not, span);
+          },
+          UnaryOperator::BitNot => {
+            self.emit(Opcode::BitNot, span);
+          },
+        };
+      }
+      ExprKind::If(condition, then_branch, else_branch) => {
+        self.compile_expr(condition)?;
+
+        let else_jump = self.emit_jump(Opcode::JumpIfFalse(0), span);
+        self.emit(Opcode::Pop, span);
+        self.compile_expr(then_branch)?;
+
+        let end_jump = self.emit_jump(Opcode::Jump(0), span);
+        self.patch_jump(else_jump);
+        self.emit(Opcode::Pop, span);
+        self.compile_expr(else_branch)?;
+        self.patch_jump(end_jump);
+      }
+      ExprKind::BinaryOp(left, op @ (BinaryOperator::And | BinaryOperator::Or), right) => {
+        self.compile_expr(left)?;
+
+        // Leaves the left operand as the result when it decides the outcome,
+        // otherwise discards it and evaluates the right operand.
+        let jump = match op {
+          BinaryOperator::And => self.emit_jump(Opcode::JumpIfFalse(0), span),
+          _ => self.emit_jump(Opcode::JumpIfTrue(0), span),
+        };
+
+        self.emit(Opcode::Pop, span);
+        self.compile_expr(right)?;
+        self.patch_jump(jump);
+      }
+      ExprKind::BinaryOp(left, op, right) => {
+        self.compile_expr(left)?;
+        self.compile_expr(right)?;
 
         match op {
           BinaryOperator::Add => {
-            self.emit(Opcode::Add);
+            self.emit(Opcode::Add, span);
           },
           BinaryOperator::Subtract => {
-            self.emit(Opcode::Subtract);
+            self.emit(Opcode::Subtract, span);
           },
           BinaryOperator::Multiply => {
-            self.emit(Opcode::Multiply);
+            self.emit(Opcode::Multiply, span);
           },
           BinaryOperator::Divide => {
-            self.emit(Opcode::Divide);
+            self.emit(Opcode::Divide, span);
           },
           BinaryOperator::Modulo => {
-            self.emit(Opcode::Modulo);
+            self.emit(Opcode::Modulo, span);
+          },
+          BinaryOperator::Power => {
+            self.emit(Opcode::Power, span);
           },
           BinaryOperator::Equal => {
-            self.emit(Opcode::Equal);
+            self.emit(Opcode::Equal, span);
           },
           BinaryOperator::NotEqual => {
-            self.emit_bytes(Opcode::Equal, Opcode::Not);
+            self.emit_bytes(Opcode::Equal, Opcode::Not, span);
           },
           BinaryOperator::LessThan => {
-            self.emit(Opcode::Less);
+            self.emit(Opcode::Less, span);
           },
           BinaryOperator::LessThanOrEqual => {
-            self.emit_bytes(Opcode::Greater, Opcode::Not);
+            self.emit_bytes(Opcode::Greater, Opcode::Not, span);
           },
           BinaryOperator::GreaterThan => {
-            self.emit(Opcode::Greater);
+            self.emit(Opcode::Greater, span);
           },
           BinaryOperator::GreaterThanOrEqual => {
-            self.emit_bytes(Opcode::Less, Opcode::Not);
+            self.emit_bytes(Opcode::Less, Opcode::Not, span);
+          },
+          BinaryOperator::BitAnd => {
+            self.emit(Opcode::BitAnd, span);
+          },
+          BinaryOperator::BitOr => {
+            self.emit(Opcode::BitOr, span);
+          },
+          BinaryOperator::BitXor => {
+            self.emit(Opcode::BitXor, span);
           },
-          BinaryOperator::And => {
-            self.emit(Opcode::And);
+          BinaryOperator::ShiftLeft => {
+            self.emit(Opcode::ShiftLeft, span);
           },
-          BinaryOperator::Or => {
-            self.emit(Opcode::Or);
+          BinaryOperator::ShiftRight => {
+            self.emit(Opcode::ShiftRight, span);
           },
-          _ => {},
+          BinaryOperator::ShiftRightUnsigned => {
+            self.emit(Opcode::ShiftRightUnsigned, span);
+          },
+          BinaryOperator::And | BinaryOperator::Or => unreachable!(),
         };
       }
-      Expr::Call(identifier, args) => {
-        self.compile_expr(identifier);
+      ExprKind::List(items) => {
+        for item in items {
+          self.compile_expr(item)?;
+        }
+
+        self.emit(Opcode::BuildList(items.len()), span);
+      }
+      ExprKind::Index(value, index) => {
+        self.compile_expr(value)?;
+        self.compile_expr(index)?;
+        self.emit(Opcode::Index, span);
+      }
+      ExprKind::Slice(value, start, end) => {
+        self.compile_expr(value)?;
+
+        for bound in [start, end].into_iter().flatten() {
+          self.compile_expr(bound)?;
+        }
+
+        self.emit(Opcode::Slice(start.is_some(), end.is_some()), span);
+      }
+      ExprKind::Range(start, end, step, inclusive) => {
+        self.compile_expr(start)?;
+        self.compile_expr(end)?;
 
-        for arg in *args.clone() {
-          self.compile_expr(&arg);
+        if let Some(step) = step {
+          self.compile_expr(step)?;
         }
+
+        self.emit(Opcode::Range(*inclusive, step.is_some()), span);
+      }
+      ExprKind::Series(op, name, iterable, body) => {
+        let (initial, combine) = match op {
+          SeriesOp::Sum => (0, Opcode::Add),
+          SeriesOp::Product => (1, Opcode::Multiply),
+        };
+
+        // A loop like `for`, with the running total in a slot below the
+        // iterable and its position.
+        let constant = self.add_constant(Object::Integer(initial));
+        self.emit(Opcode::Constant(constant), span);
+
+        let total = self.depth - 1;
+
+        self.compile_expr(iterable)?;
+
+        let constant = self.add_constant(Object::Integer(0));
+        self.emit(Opcode::Constant(constant), span);
+
+        let start = self.bytecode.codes.len();
+        let exit_jump = self.emit_jump(Opcode::IterNext(total + 1, 0), span);
+
+        self.locals.push((name.clone(), total + 3));
+        self.emit(Opcode::GetLocal(total), span);
+        self.compile_expr(body)?;
+        self.locals.pop();
+
+        self.emit(combine, span);
+        self.emit(Opcode::SetLocal(total), span);
+        self.emit_bytes(Opcode::Pop, Opcode::Pop, span);
+        self.emit(Opcode::Loop(start), span);
+        self.patch_jump(exit_jump);
+        self.emit_bytes(Opcode::Pop, Opcode::Pop, span);
+      }
+      ExprKind::Let(bindings, body) => {
+        let scope = self.locals.len();
+
+        for (name, value) in bindings {
+          self.compile_expr(value)?;
+          self.locals.push((name.clone(), self.depth - 1));
+        }
+
+        self.compile_expr(body)?;
+        self.locals.truncate(scope);
+        self.emit(Opcode::PopBelow(bindings.len()), span);
+      }
+      ExprKind::Lambda(params, body) => {
+        self.compile_function("lambda", params, body, span)?;
+      }
+      ExprKind::Call(identifier, args) => {
+        self.compile_expr(identifier)?;
+
+        for arg in args {
+          self.compile_expr(arg)?;
+        }
+
+        let count = match u8::try_from(args.len()) {
+          Ok(count) => count,
+          Err(_) => {
+            return Err(Error::Compile(format!("too many arguments: {} (limit is {})", args.len(), u8::MAX), span));
+          }
+        };
+
+        self.emit(Opcode::Call(count), span);
       }
-      _ => {}
     }
+
+    Ok(())
   }
 
-  fn compile_stmt(&mut self, stmt: &Stmt) {
+  /// Compiles a statement, returning whether it leaves a value on the
+  /// stack.
+  fn compile_stmt(&mut self, stmt: &Stmt) -> Result<bool> {
     match stmt {
       Stmt::Expr(expr) => {
-        self.compile_expr(expr);
+        self.compile_expr(expr)?;
+      }
+      Stmt::Assign(name, value) => {
+        self.compile_expr(value)?;
+
+        // A loop variable or `let` binding in scope is assigned in place.
+        if let Some(slot) = self.resolve_local(name) {
+          self.emit(Opcode::SetLocal(slot), value.span);
+        } else {
+          let name = self.add_name(name);
+          self.emit(Opcode::SetGlobal(name), value.span);
+        }
+      }
+      Stmt::Function(name, params, body) => {
+        self.compile_function(name, params, body, body.span)?;
+
+        let name = self.add_name(name);
+        self.emit(Opcode::SetGlobal(name), body.span);
+      }
+      Stmt::Block(stmts) => {
+        return self.compile_stmts(stmts);
+      }
+      Stmt::While(condition, body) => {
+        let span = condition.span;
+        let start = self.bytecode.codes.len();
+
+        self.compile_expr(condition)?;
+
+        let exit_jump = self.emit_jump(Opcode::JumpIfFalse(0), span);
+        self.emit(Opcode::Pop, span);
+        self.compile_body(body, span)?;
+        self.emit(Opcode::Loop(start), span);
+        self.patch_jump(exit_jump);
+
+        // The condition is still on the stack when the loop exits.
+        self.depth += 1;
+        self.emit(Opcode::Pop, span);
+
+        return Ok(false);
+      }
+      Stmt::For(name, iterable, body) => {
+        let span = iterable.span;
+
+        // The iterable and the position in it sit in two slots below the
+        // loop variable.
+        self.compile_expr(iterable)?;
+
+        let slot = self.depth - 1;
+        let constant = self.add_constant(Object::Integer(0));
+        self.emit(Opcode::Constant(constant), span);
+
+        let start = self.bytecode.codes.len();
+        let exit_jump = self.emit_jump(Opcode::IterNext(slot, 0), span);
+
+        self.locals.push((name.clone(), slot + 2));
+        self.compile_body(body, span)?;
+        self.locals.pop();
+
+        self.emit(Opcode::Pop, span);
+        self.emit(Opcode::Loop(start), span);
+        self.patch_jump(exit_jump);
+        self.emit_bytes(Opcode::Pop, Opcode::Pop, span);
+
+        return Ok(false);
       }
     }
+
+    Ok(true)
   }
 
-  pub fn compile(&mut self, program: &Program) -> Bytecode {
-    for stmt in program {
-      self.compile_stmt(stmt);
+  /// Compiles statements in order, keeping only the value of the last one
+  /// and returning whether there is one.
+  fn compile_stmts(&mut self, stmts: &[Stmt]) -> Result<bool> {
+    let mut has_value = false;
+
+    for stmt in stmts {
+      if has_value {
+        let span = self.bytecode.spans.last().copied().unwrap_or_default();
+        self.emit(Opcode::Pop, span);
+      }
+
+      has_value = self.compile_stmt(stmt)?;
+    }
+
+    Ok(has_value)
+  }
+
+  fn compile_body(&mut self, body: &[Stmt], span: Span) -> Result<()> {
+    if self.compile_stmts(body)? {
+      self.emit(Opcode::Pop, span);
+    }
+
+    Ok(())
+  }
+
+  pub fn compile(&mut self, program: &Program) -> Result<Bytecode> {
+    let has_value = self.compile_stmts(program)?;
+    let span = self.bytecode.spans.last().copied().unwrap_or_default();
+
+    if !has_value {
+      let constant = self.add_constant(Object::Unit);
+      self.emit(Opcode::Constant(constant), span);
     }
 
-    self.emit(Opcode::Return);
+    self.emit(Opcode::Return, span);
 
-    self.bytecode.clone()
+    Ok(self.bytecode.clone())
   }
 }
 
@@ -130,9 +533,9 @@ mod tests {
   #[test]
   fn compile() {
     let mut lexer = Lexer::new("true && 10 > 1");
-    let mut parser = Parser::new(lexer.lex());
+    let mut parser = Parser::new(lexer.lex().unwrap());
     let mut compiler = Compiler::new();
-    let mut bytecode = compiler.compile(&parser.parse());
+    let bytecode = compiler.compile(&parser.parse().unwrap()).unwrap();
 
     println!("{:#?}", bytecode);
   }
@@ -0,0 +1,165 @@
+use std::fmt;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Complex {
+  pub re: f64,
+  pub im: f64,
+}
+
+impl Complex {
+  pub fn new(re: f64, im: f64) -> Self {
+    Self {
+      re,
+      im,
+    }
+  }
+
+  pub fn from_polar(r: f64, theta: f64) -> Self {
+    Complex::new(r * theta.cos(), r * theta.sin())
+  }
+
+  pub fn abs(&self) -> f64 {
+    self.re.hypot(self.im)
+  }
+
+  pub fn arg(&self) -> f64 {
+    self.im.atan2(self.re)
+  }
+
+  pub fn conj(&self) -> Self {
+    Complex::new(self.re, -self.im)
+  }
+
+  pub fn exp(&self) -> Self {
+    Complex::from_polar(self.re.exp(), self.im)
+  }
+
+  /// The principal natural logarithm, with the imaginary part in (-π, π].
+  pub fn ln(&self) -> Self {
+    Complex::new(self.abs().ln(), self.arg())
+  }
+
+  /// The principal square root, with a non-negative real part.
+  pub fn sqrt(&self) -> Self {
+    let r = self.abs();
+    let re = ((r + self.re) / 2.0).sqrt();
+    let im = ((r - self.re) / 2.0).sqrt();
+
+    Complex::new(re, if self.im.is_sign_negative() { -im } else { im })
+  }
+
+  /// Raises to a complex power. Whole exponents multiply repeatedly so that
+  /// results such as `i^2` come out exact.
+  pub fn pow(&self, exp: Complex) -> Self {
+    if exp.im == 0.0 && exp.re.fract() == 0.0 && exp.re.abs() <= 1024.0 {
+      let mut result = Complex::new(1.0, 0.0);
+      let mut base = if exp.re < 0.0 { Complex::new(1.0, 0.0) / *self } else { *self };
+      let mut n = exp.re.abs() as u32;
+
+      while n > 0 {
+        if n & 1 == 1 {
+          result = result * base;
+        }
+
+        base = base * base;
+        n >>= 1;
+      }
+
+      return result;
+    }
+
+    if self.re == 0.0 && self.im == 0.0 {
+      return Complex::new(0.0, 0.0);
+    }
+
+    (exp * self.ln()).exp()
+  }
+}
+
+impl Add for Complex {
+  type Output = Complex;
+
+  fn add(self, other: Complex) -> Complex {
+    Complex::new(self.re + other.re, self.im + other.im)
+  }
+}
+
+impl Sub for Complex {
+  type Output = Complex;
+
+  fn sub(self, other: Complex) -> Complex {
+    Complex::new(self.re - other.re, self.im - other.im)
+  }
+}
+
+impl Mul for Complex {
+  type Output = Complex;
+
+  fn mul(self, other: Complex) -> Complex {
+    Complex::new(self.re * other.re - self.im * other.im, self.re * other.im + self.im * other.re)
+  }
+}
+
+impl Div for Complex {
+  type Output = Complex;
+
+  fn div(self, other: Complex) -> Complex {
+    let denom = other.re * other.re + other.im * other.im;
+    let re = (self.re * other.re + self.im * other.im) / denom;
+    let im = (self.im * other.re - self.re * other.im) / denom;
+
+    Complex::new(re, im)
+  }
+}
+
+impl Neg for Complex {
+  type Output = Complex;
+
+  fn neg(self) -> Complex {
+    Complex::new(-self.re, -self.im)
+  }
+}
+
+/// Writes `3+4i`, `3-4i`, `4i` or `-i`, applying any precision to both parts.
+impl fmt::Display for Complex {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let part = |value: f64| match f.precision() {
+      Some(places) => format!("{:.*}", places, value),
+      None => value.to_string(),
+    };
+
+    let im = if self.im.abs() == 1.0 { String::new() } else { part(self.im.abs()) };
+    let negative = self.im.is_sign_negative();
+
+    if self.re == 0.0 {
+      write!(f, "{}{}i", if negative { "-" } else { "" }, im)
+    } else {
+      write!(f, "{}{}{}i", part(self.re), if negative { "-" } else { "+" }, im)
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::complex::Complex;
+
+  #[test]
+  fn complex() {
+    let z = Complex::new(3.0, 4.0);
+    let i = Complex::new(0.0, 1.0);
+
+    assert_eq!(z.abs(), 5.0);
+    assert_eq!(z * z.conj(), Complex::new(25.0, 0.0));
+    assert_eq!(z / z, Complex::new(1.0, 0.0));
+    assert_eq!(i.pow(Complex::new(2.0, 0.0)), Complex::new(-1.0, 0.0));
+    assert_eq!(Complex::new(-4.0, 0.0).sqrt(), Complex::new(0.0, 2.0));
+    assert!((Complex::new(0.0, std::f64::consts::PI).exp() - Complex::new(-1.0, 0.0)).abs() < 1e-15);
+
+    assert_eq!(z.to_string(), "3+4i");
+    assert_eq!(z.conj().to_string(), "3-4i");
+    assert_eq!(i.to_string(), "i");
+    assert_eq!((-i * Complex::new(2.5, 0.0)).to_string(), "-2.5i");
+    assert_eq!(format!("{:.2}", Complex::new(1.0, -1.0 / 3.0)), "1.00-0.33i");
+  }
+}
@@ -0,0 +1,364 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::UNIX_EPOCH;
+use crate::ast::BinaryOperator;
+use crate::decimal::DecimalContext;
+use crate::json::{self, Value};
+use crate::number;
+use crate::object::Object;
+use crate::rational::Rational;
+
+/// A currency code of three uppercase ASCII letters, such as `USD`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Currency([u8; 3]);
+
+impl Currency {
+  pub fn parse(code: &str) -> Option<Self> {
+    match code.as_bytes() {
+      [a, b, c] if code.bytes().all(|byte| byte.is_ascii_uppercase()) => Some(Currency([*a, *b, *c])),
+      _ => None,
+    }
+  }
+
+  pub fn as_str(&self) -> &str {
+    std::str::from_utf8(&self.0).expect("currency codes are ASCII")
+  }
+}
+
+impl fmt::Display for Currency {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+/// An amount of money in a single currency.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Money {
+  pub amount: Object,
+  pub currency: Currency,
+}
+
+impl Money {
+  pub fn new(amount: Object, currency: Currency) -> Self {
+    Self {
+      amount,
+      currency,
+    }
+  }
+}
+
+/// Whole and decimal amounts print as they are, anything else to the cent.
+impl fmt::Display for Money {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match (&self.amount, f.precision()) {
+      (Object::Integer(_) | Object::BigInt(_) | Object::Decimal(_), None) => write!(f, "{} {}", self.amount, self.currency),
+      (amount, places) => write!(f, "{:.*} {}", places.unwrap_or(2), amount, self.currency),
+    }
+  }
+}
+
+/// Days from 1970-01-01 to the given proleptic Gregorian date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+  let year = if month <= 2 { year - 1 } else { year };
+  let era = year.div_euclid(400);
+  let year_of_era = year - era * 400;
+  let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+  let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+  era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+  let days = days + 719468;
+  let era = days.div_euclid(146097);
+  let day_of_era = days - era * 146097;
+  let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+  let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+  let month = (5 * day_of_year + 2) / 153;
+  let day = day_of_year - (153 * month + 2) / 5 + 1;
+  let month = if month < 10 { month + 3 } else { month - 9 };
+
+  (year_of_era + era * 400 + if month <= 2 { 1 } else { 0 }, month, day)
+}
+
+/// Parses a UTC timestamp such as `2024-05-01`, `2024-05-01T12:30` or
+/// `2024-05-01T12:30:00Z` into Unix seconds.
+pub fn parse_timestamp(text: &str) -> Option<i64> {
+  let text = text.strip_suffix('Z').unwrap_or(text);
+  let (date, time) = match text.split_once(['T', ' ']) {
+    Some((date, time)) => (date, time),
+    None => (text, "00:00"),
+  };
+
+  let fields = |text: &str, separator: char| -> Option<Vec<i64>> {
+    text.split(separator).map(|field| field.parse().ok()).collect()
+  };
+
+  let (year, month, day) = match fields(date, '-')?[..] {
+    [year, month, day] if (1..=12).contains(&month) && (1..=31).contains(&day) => (year, month, day),
+    _ => return None,
+  };
+
+  let seconds = match fields(time, ':')?[..] {
+    [hour, minute] => hour * 3600 + minute * 60,
+    [hour, minute, second] => hour * 3600 + minute * 60 + second,
+    _ => return None,
+  };
+
+  Some(days_from_civil(year, month, day) * 86400 + seconds)
+}
+
+/// Formats Unix seconds as an ISO 8601 UTC timestamp.
+pub fn format_timestamp(seconds: i64) -> String {
+  let (year, month, day) = civil_from_days(seconds.div_euclid(86400));
+  let time = seconds.rem_euclid(86400);
+
+  format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, time / 3600, time / 60 % 60, time % 60)
+}
+
+/// Reads a rate exactly where possible, so conversions in decimal mode are
+/// rounded once.
+fn parse_rate(text: &str) -> Option<Object> {
+  let rate = match Rational::from_decimal_str(text.trim()) {
+    Some(rate) => number::from_rational(rate),
+    None => Object::Number(text.trim().parse::<f64>().ok().filter(|rate| rate.is_finite())?),
+  };
+
+  match number::compare(&rate, &Object::Integer(0)) {
+    Some(Ordering::Greater) => Some(rate),
+    _ => None,
+  }
+}
+
+/// Exchange rates, each the number of units of a currency that one unit of
+/// the base currency buys.
+#[derive(Debug, Clone, Default)]
+pub struct Rates {
+  rates: HashMap<Currency, Object>,
+  timestamp: Option<i64>,
+}
+
+impl Rates {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn insert(&mut self, currency: Currency, rate: Object) {
+    self.rates.insert(currency, rate);
+  }
+
+  pub fn rate(&self, currency: Currency) -> Option<&Object> {
+    self.rates.get(&currency)
+  }
+
+  pub fn len(&self) -> usize {
+    self.rates.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.rates.is_empty()
+  }
+
+  /// When the rates were published, in Unix seconds.
+  pub fn timestamp(&self) -> Option<i64> {
+    self.timestamp
+  }
+
+  pub fn set_timestamp(&mut self, timestamp: Option<i64>) {
+    self.timestamp = timestamp;
+  }
+
+  fn set_base(&mut self, code: &str) -> Result<(), String> {
+    match Currency::parse(code) {
+      Some(base) => {
+        self.rates.entry(base).or_insert(Object::Integer(1));
+        Ok(())
+      }
+      None => Err(format!("invalid base currency '{}'", code)),
+    }
+  }
+
+  fn set_rate(&mut self, code: &str, rate: &str) -> Result<(), String> {
+    let currency = Currency::parse(code).ok_or_else(|| format!("invalid currency code '{}'", code))?;
+    let rate = parse_rate(rate).ok_or_else(|| format!("invalid rate '{}' for {}", rate, code))?;
+
+    self.rates.insert(currency, rate);
+    Ok(())
+  }
+
+  /// Reads `{"base": "USD", "timestamp": ..., "rates": {"EUR": 0.92, ...}}`,
+  /// where the timestamp is Unix seconds or an ISO 8601 string and may be
+  /// given as `date` instead.
+  pub fn from_json(source: &str) -> Result<Self, String> {
+    let value = json::parse(source)?;
+    let mut rates = Rates::new();
+
+    match value.get("rates") {
+      Some(Value::Object(members)) => {
+        for (code, rate) in members {
+          match rate {
+            Value::Number(rate) => rates.set_rate(code, rate)?,
+            _ => return Err(format!("rate for {} must be a number", code)),
+          }
+        }
+      }
+      _ => return Err("expected a \"rates\" object".to_string()),
+    }
+
+    if let Some(Value::String(base)) = value.get("base") {
+      rates.set_base(base)?;
+    }
+
+    rates.timestamp = match value.get("timestamp").or_else(|| value.get("date")) {
+      Some(Value::Number(seconds)) => Some(seconds.parse().map_err(|_| format!("invalid timestamp {}", seconds))?),
+      Some(Value::String(text)) => Some(parse_timestamp(text).ok_or_else(|| format!("invalid timestamp '{}'", text))?),
+      _ => None,
+    };
+
+    Ok(rates)
+  }
+
+  /// Reads `code,rate` lines. `base,USD` and `timestamp,2024-05-01` lines set
+  /// the base currency and timestamp; a `currency,rate` header, blank lines
+  /// and `#` comments are skipped.
+  pub fn from_csv(source: &str) -> Result<Self, String> {
+    let mut rates = Rates::new();
+
+    for (index, line) in source.lines().enumerate() {
+      let line = line.trim();
+
+      if line.is_empty() || line.starts_with('#') {
+        continue;
+      }
+
+      let (key, value) = line.split_once(',').map(|(key, value)| (key.trim(), value.trim())).unwrap_or((line, ""));
+
+      let result = match key {
+        "currency" => Ok(()),
+        "base" => rates.set_base(value),
+        "timestamp" | "date" => match parse_timestamp(value).or_else(|| value.parse().ok()) {
+          Some(timestamp) => {
+            rates.timestamp = Some(timestamp);
+            Ok(())
+          }
+          None => Err(format!("invalid timestamp '{}'", value)),
+        },
+        code => rates.set_rate(code, value),
+      };
+
+      result.map_err(|message| format!("line {}: {}", index + 1, message))?;
+    }
+
+    Ok(rates)
+  }
+
+  /// Loads a `.json` or CSV rates file. Without a timestamp in the file, the
+  /// file's modification time is used.
+  pub fn load(path: &Path) -> Result<Self, String> {
+    let source = std::fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+
+    let mut rates = match path.extension().and_then(|extension| extension.to_str()) {
+      Some(extension) if extension.eq_ignore_ascii_case("json") => Rates::from_json(&source),
+      _ => Rates::from_csv(&source),
+    }
+    .map_err(|message| format!("{}: {}", path.display(), message))?;
+
+    if rates.timestamp.is_none() {
+      let modified = std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+      rates.timestamp = modified.and_then(|time| time.duration_since(UNIX_EPOCH).ok()).map(|age| age.as_secs() as i64);
+    }
+
+    Ok(rates)
+  }
+
+  pub fn convert(&self, money: &Money, to: Currency, context: &DecimalContext) -> Result<Money, String> {
+    if money.currency == to {
+      return Ok(money.clone());
+    }
+
+    let rate = |currency: Currency| self.rate(currency).ok_or_else(|| format!("no exchange rate for {}", currency));
+    let ratio = number::arithmetic(&BinaryOperator::Divide, rate(to)?, rate(money.currency)?, context)?;
+    let amount = number::arithmetic(&BinaryOperator::Multiply, &money.amount, &ratio, context)?;
+
+    Ok(Money::new(amount, to))
+  }
+}
+
+/// Arithmetic where either operand is money. Amounts in different
+/// currencies must be converted with `to` before they are combined.
+pub fn arithmetic(op: &BinaryOperator, left: &Object, right: &Object, context: &DecimalContext) -> Result<Object, String> {
+  let invalid = || format!("invalid operands for '{}': {} and {}", op, left.type_name(), right.type_name());
+
+  match (left, right) {
+    (Object::Money(left), Object::Money(right)) => {
+      if left.currency != right.currency {
+        return Err(format!("cannot combine {} and {} without converting one with 'to'", left.currency, right.currency));
+      }
+
+      match op {
+        BinaryOperator::Add | BinaryOperator::Subtract | BinaryOperator::Modulo => {
+          let amount = number::arithmetic(op, &left.amount, &right.amount, context)?;
+          Ok(Object::Money(Rc::new(Money::new(amount, left.currency))))
+        }
+        BinaryOperator::Divide => number::arithmetic(op, &left.amount, &right.amount, context),
+        _ => Err(invalid()),
+      }
+    }
+    (Object::Money(left), right) if right.is_real() && matches!(op, BinaryOperator::Multiply | BinaryOperator::Divide) => {
+      let amount = number::arithmetic(op, &left.amount, right, context)?;
+      Ok(Object::Money(Rc::new(Money::new(amount, left.currency))))
+    }
+    (left, Object::Money(right)) if left.is_real() && matches!(op, BinaryOperator::Multiply) => {
+      let amount = number::arithmetic(op, left, &right.amount, context)?;
+      Ok(Object::Money(Rc::new(Money::new(amount, right.currency))))
+    }
+    _ => Err(invalid()),
+  }
+}
+
+/// Orders two amounts of money in the same currency.
+pub fn compare(left: &Object, right: &Object) -> Result<Option<Ordering>, String> {
+  match (left, right) {
+    (Object::Money(left), Object::Money(right)) if left.currency == right.currency => {
+      Ok(number::compare(&left.amount, &right.amount))
+    }
+    (Object::Money(left), Object::Money(right)) => {
+      Err(format!("cannot compare {} with {} without converting one with 'to'", left.currency, right.currency))
+    }
+    _ => Err(format!("cannot compare {} with {}", left.type_name(), right.type_name())),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::currency::{self, Currency, Money, Rates};
+  use crate::decimal::DecimalContext;
+  use crate::object::Object;
+
+  #[test]
+  fn rates() {
+    let json = r#"{"base": "USD", "timestamp": 1714521600, "rates": {"EUR": 0.8, "JPY": 150}}"#;
+    let csv = "currency,rate\n# comment\nbase,USD\ntimestamp,2024-05-01T00:00:00Z\nEUR,0.8\nJPY,150\n";
+    let (usd, eur, jpy) = (Currency::parse("USD").unwrap(), Currency::parse("EUR").unwrap(), Currency::parse("JPY").unwrap());
+
+    for rates in [Rates::from_json(json).unwrap(), Rates::from_csv(csv).unwrap()] {
+      assert_eq!(rates.len(), 3);
+      assert_eq!(rates.timestamp(), Some(1714521600));
+
+      let money = Money::new(Object::Integer(100), usd);
+      let context = DecimalContext::default();
+      assert_eq!(rates.convert(&money, eur, &context).unwrap().to_string(), "80 EUR");
+      assert_eq!(rates.convert(&Money::new(Object::Integer(1), jpy), eur, &context).unwrap().to_string(), "0.01 EUR");
+    }
+
+    assert_eq!(currency::format_timestamp(1714521600), "2024-05-01T00:00:00Z");
+    assert_eq!(currency::parse_timestamp("1969-12-31T23:59:59Z"), Some(-1));
+    assert!(Currency::parse("usd").is_none());
+    assert!(Rates::from_csv("EUR,-1").is_err());
+    assert!(Rates::from_json(r#"{"rates": {"EUR": "0.8"}}"#).is_err());
+    assert!(Rates::from_json(&"[".repeat(100_000)).unwrap_err().contains("nesting too deep"));
+  }
+}
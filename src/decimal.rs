@@ -0,0 +1,268 @@
+use std::cmp::Ordering;
+use std::fmt;
+use crate::bigint::BigInt;
+
+/// Largest number of digits a decimal may carry after the point.
+pub const MAX_SCALE: u32 = 36;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum RoundingMode {
+  HalfEven,
+  HalfUp,
+  HalfDown,
+  /// Away from zero.
+  Up,
+  /// Toward zero.
+  Down,
+  Ceiling,
+  Floor,
+}
+
+impl RoundingMode {
+  /// Every mode, paired with the name scripts select it by.
+  pub const ALL: [(&'static str, RoundingMode); 7] = [
+    ("half_even", RoundingMode::HalfEven),
+    ("half_up", RoundingMode::HalfUp),
+    ("half_down", RoundingMode::HalfDown),
+    ("up", RoundingMode::Up),
+    ("down", RoundingMode::Down),
+    ("ceiling", RoundingMode::Ceiling),
+    ("floor", RoundingMode::Floor),
+  ];
+
+  pub fn from_name(name: &str) -> Option<RoundingMode> {
+    RoundingMode::ALL.iter().find(|(mode, _)| *mode == name).map(|(_, mode)| *mode)
+  }
+
+  /// Whether an inexact truncated quotient should step away from zero,
+  /// given its sign, its parity and how the discarded part compares to a half.
+  fn round_away(self, negative: bool, odd: bool, half: Ordering) -> bool {
+    match self {
+      RoundingMode::HalfEven => half == Ordering::Greater || (half == Ordering::Equal && odd),
+      RoundingMode::HalfUp => half != Ordering::Less,
+      RoundingMode::HalfDown => half == Ordering::Greater,
+      RoundingMode::Up => true,
+      RoundingMode::Down => false,
+      RoundingMode::Ceiling => !negative,
+      RoundingMode::Floor => negative,
+    }
+  }
+
+  /// Rounds a float to a whole number.
+  pub fn round_f64(self, value: f64) -> f64 {
+    let truncated = value.trunc();
+    let discarded = (value - truncated).abs();
+
+    if discarded == 0.0 || !value.is_finite() {
+      return value;
+    }
+
+    let half = discarded.partial_cmp(&0.5).unwrap_or(Ordering::Less);
+
+    if self.round_away(value < 0.0, truncated % 2.0 != 0.0, half) {
+      truncated + value.signum()
+    } else {
+      truncated
+    }
+  }
+}
+
+pub fn pow10(exp: u32) -> BigInt {
+  BigInt::from_i64(10).pow(exp)
+}
+
+/// Divides `num` by `den`, rounding the quotient to a whole number with
+/// `mode`. Returns `None` when dividing by zero.
+pub fn round_quotient(num: &BigInt, den: &BigInt, mode: RoundingMode) -> Option<BigInt> {
+  let (quotient, remainder) = num.divmod(den)?;
+
+  if remainder.is_zero() {
+    return Some(quotient);
+  }
+
+  let two = BigInt::from_i64(2);
+  let negative = num.is_negative() != den.is_negative();
+  let odd = !quotient.divmod(&two)?.1.is_zero();
+  let half = remainder.abs().mul(&two).cmp(&den.abs());
+
+  if mode.round_away(negative, odd, half) {
+    Some(quotient.add(&BigInt::from_i64(if negative { -1 } else { 1 })))
+  } else {
+    Some(quotient)
+  }
+}
+
+/// The scale and rounding applied to the result of every decimal operation.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DecimalContext {
+  pub scale: u32,
+  pub rounding: RoundingMode,
+}
+
+impl Default for DecimalContext {
+  fn default() -> Self {
+    Self {
+      scale: 2,
+      rounding: RoundingMode::HalfEven,
+    }
+  }
+}
+
+/// A base-10 fixed-point number worth `units / 10^scale`, so `0.1` is
+/// stored exactly and prints with all of its digits.
+#[derive(Debug, Copy, Clone)]
+pub struct Decimal {
+  units: i128,
+  scale: u32,
+}
+
+impl Decimal {
+  pub fn new(units: i128, scale: u32) -> Self {
+    Self {
+      units,
+      scale,
+    }
+  }
+
+  /// Parses a literal such as `19.99` or `1.5e-3`, keeping every digit
+  /// written after the point. Returns `None` if the value does not fit.
+  pub fn parse(literal: &str) -> Option<Self> {
+    let (mantissa, exponent) = match literal.find(['e', 'E']) {
+      Some(index) => (&literal[..index], literal[index + 1..].parse::<i64>().ok()?),
+      None => (literal, 0),
+    };
+
+    let (whole, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let mut units = BigInt::parse(&format!("{}{}", whole, fraction), 10)?;
+    let mut scale = (fraction.len() as i64).checked_sub(exponent)?;
+
+    if scale < 0 {
+      units = units.mul(&pow10(u32::try_from(-scale).ok()?));
+      scale = 0;
+    }
+
+    if scale > MAX_SCALE as i64 {
+      return None;
+    }
+
+    Some(Decimal::new(units.to_i128()?, scale as u32))
+  }
+
+  /// Rounds the fraction `num / den` to `scale` digits after the point.
+  /// Returns `None` if `den` is zero or the result does not fit.
+  pub fn from_fraction(num: &BigInt, den: &BigInt, scale: u32, mode: RoundingMode) -> Option<Self> {
+    let units = round_quotient(&num.mul(&pow10(scale)), den, mode)?;
+    Some(Decimal::new(units.to_i128()?, scale))
+  }
+
+  /// The exact value as a numerator and positive denominator.
+  pub fn fraction(&self) -> (BigInt, BigInt) {
+    (BigInt::from_i128(self.units), pow10(self.scale))
+  }
+
+  pub fn scale(&self) -> u32 {
+    self.scale
+  }
+
+  pub fn is_zero(&self) -> bool {
+    self.units == 0
+  }
+
+  pub fn to_f64(&self) -> f64 {
+    self.units as f64 / 10f64.powi(self.scale as i32)
+  }
+
+  pub fn rescale(&self, scale: u32, mode: RoundingMode) -> Option<Self> {
+    let (num, den) = self.fraction();
+    Decimal::from_fraction(&num, &den, scale, mode)
+  }
+
+  pub fn checked_neg(self) -> Option<Self> {
+    Some(Decimal::new(self.units.checked_neg()?, self.scale))
+  }
+}
+
+impl PartialEq for Decimal {
+  fn eq(&self, other: &Self) -> bool {
+    self.cmp(other) == Ordering::Equal
+  }
+}
+
+impl Eq for Decimal {}
+
+impl PartialOrd for Decimal {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Ord for Decimal {
+  fn cmp(&self, other: &Self) -> Ordering {
+    let (left, right) = (BigInt::from_i128(self.units), BigInt::from_i128(other.units));
+    left.mul(&pow10(other.scale)).cmp(&right.mul(&pow10(self.scale)))
+  }
+}
+
+/// Prints every digit of the scale, so `1.50` keeps its trailing zero. A
+/// precision (`{:.4}`) rounds half to even.
+impl fmt::Display for Decimal {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let value = match f.precision() {
+      Some(places) => match self.rescale(places as u32, RoundingMode::HalfEven) {
+        Some(value) => value,
+        None => return write!(f, "{:.*}", places, self.to_f64()),
+      },
+      None => *self,
+    };
+
+    let digits = format!("{:0width$}", value.units.unsigned_abs(), width = value.scale as usize + 1);
+    let (whole, fraction) = digits.split_at(digits.len() - value.scale as usize);
+    let sign = if value.units < 0 { "-" } else { "" };
+
+    match fraction {
+      "" => write!(f, "{}{}", sign, whole),
+      _ => write!(f, "{}{}.{}", sign, whole, fraction),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::bigint::BigInt;
+  use crate::decimal::{Decimal, RoundingMode};
+
+  #[test]
+  fn decimal() {
+    assert_eq!(Decimal::parse("19.90").unwrap().to_string(), "19.90");
+    assert_eq!(Decimal::parse("1.5e-3").unwrap().to_string(), "0.0015");
+    assert_eq!(Decimal::parse("2.5E2").unwrap().to_string(), "250");
+    assert_eq!(Decimal::parse("0.1"), Decimal::parse("0.100"));
+    assert_eq!(format!("{:.1}", Decimal::parse("0.25").unwrap()), "0.2");
+
+    let testcases = vec![
+      (RoundingMode::HalfEven, ["2", "-2", "4", "-4", "3"]),
+      (RoundingMode::HalfUp, ["3", "-3", "4", "-4", "3"]),
+      (RoundingMode::HalfDown, ["2", "-2", "3", "-3", "3"]),
+      (RoundingMode::Up, ["3", "-3", "4", "-4", "3"]),
+      (RoundingMode::Down, ["2", "-2", "3", "-3", "2"]),
+      (RoundingMode::Ceiling, ["3", "-2", "4", "-3", "3"]),
+      (RoundingMode::Floor, ["2", "-3", "3", "-4", "2"]),
+    ];
+
+    for (mode, expected) in testcases {
+      let actual: Vec<String> = [(5, 2), (-5, 2), (7, 2), (-7, 2), (13, 5)].iter()
+        .map(|(num, den)| {
+          let (num, den) = (BigInt::from_i64(*num), BigInt::from_i64(*den));
+          Decimal::from_fraction(&num, &den, 0, mode).unwrap().to_string()
+        })
+        .collect();
+
+      assert_eq!(actual, expected, "{:?}", mode);
+    }
+
+    assert_eq!(RoundingMode::HalfEven.round_f64(2.5), 2.0);
+    assert_eq!(RoundingMode::HalfDown.round_f64(-3.5), -3.0);
+    assert_eq!(RoundingMode::Floor.round_f64(-0.1), -1.0);
+    assert!(Decimal::from_fraction(&BigInt::from_i64(1), &BigInt::zero(), 2, RoundingMode::Up).is_none());
+  }
+}
@@ -0,0 +1,200 @@
+use crate::compiler::Compiler;
+use crate::currency::Rates;
+use crate::decimal::DecimalContext;
+use crate::error::Result;
+use crate::lexer::Lexer;
+use crate::native::Arity;
+use crate::object::Object;
+use crate::parser::Parser;
+use crate::vm::VM;
+
+/// Runs source text through the lexer, parser, compiler and a VM whose
+/// globals and host bindings persist between calls to `eval`.
+#[derive(Debug, Default)]
+pub struct Engine {
+  vm: VM,
+}
+
+impl Engine {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn register_fn<F>(&mut self, name: &str, arity: Arity, pure: bool, function: F) -> &mut Self
+  where
+    F: Fn(&[Object]) -> std::result::Result<Object, String> + 'static,
+  {
+    self.vm.register_fn(name, arity, pure, function);
+    self
+  }
+
+  pub fn register_const(&mut self, name: &str, value: Object) -> &mut Self {
+    self.vm.register_const(name, value);
+    self
+  }
+
+  /// See [`VM::set_decimal_mode`].
+  pub fn decimal_mode(&mut self, context: Option<DecimalContext>) -> &mut Self {
+    self.vm.set_decimal_mode(context);
+    self
+  }
+
+  /// See [`VM::set_rates`].
+  pub fn rates(&mut self, rates: Rates) -> &mut Self {
+    self.vm.set_rates(rates);
+    self
+  }
+
+  /// See [`VM::set_max_call_depth`].
+  pub fn max_call_depth(&mut self, depth: usize) -> &mut Self {
+    self.vm.set_max_call_depth(depth);
+    self
+  }
+
+  /// See [`VM::set_max_iterations`].
+  pub fn max_iterations(&mut self, iterations: usize) -> &mut Self {
+    self.vm.set_max_iterations(iterations);
+    self
+  }
+
+  pub fn eval(&mut self, source: &str) -> Result<Object> {
+    let tokens = Lexer::new(source).decimal_literals(self.vm.decimal_mode().is_some()).lex()?;
+    let program = Parser::new(tokens).parse()?;
+    let bytecode = Compiler::new().compile(&program)?;
+
+    self.vm.run(bytecode)
+  }
+
+  pub fn vm(&self) -> &VM {
+    &self.vm
+  }
+
+  pub fn vm_mut(&mut self) -> &mut VM {
+    &mut self.vm
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::cell::RefCell;
+  use std::rc::Rc;
+  use crate::currency::Rates;
+  use crate::decimal::{DecimalContext, RoundingMode};
+  use crate::engine::Engine;
+  use crate::error::Error;
+  use crate::native::Arity;
+  use crate::object::Object;
+
+  #[test]
+  fn host_bindings() {
+    let mut engine = Engine::new();
+
+    engine
+      .register_const("vat", Object::Number(0.2))
+      .register_fn("tier", Arity::Fixed(1), true, |args| match args[0].to_f64() {
+        Some(qty) if qty >= 100.0 => Ok(Object::Number(0.8)),
+        Some(_) => Ok(Object::Number(1.0)),
+        None => Err("quantity must be a number".to_string()),
+      })
+      .register_fn("total", Arity::Variadic(0), false, |args| {
+        Ok(Object::Number(args.iter().filter_map(Object::to_f64).sum()))
+      });
+
+    assert_eq!(engine.eval("10 * tier(150) * (1 + vat)").unwrap(), Object::Number(9.6));
+    assert_eq!(engine.eval("total() + total(1, 2, 3)").unwrap(), Object::Number(6.0));
+    assert!(engine.vm().native("tier").unwrap().pure);
+    assert!(!engine.vm().native("total").unwrap().pure);
+
+    assert!(matches!(engine.eval("tier(true)"), Err(Error::Runtime(..))));
+    assert!(matches!(engine.eval("tier(1, 2)"), Err(Error::Runtime(..))));
+    assert!(matches!(engine.eval("vat = 0.1"), Err(Error::Runtime(..))));
+  }
+
+  #[test]
+  fn short_circuit_order() {
+    let calls = Rc::new(RefCell::new(Vec::new()));
+    let mut engine = Engine::new();
+
+    let log = calls.clone();
+    engine.register_fn("f", Arity::Fixed(1), false, move |args| {
+      log.borrow_mut().push(args[0].clone());
+      Ok(args[0].clone())
+    });
+
+    assert_eq!(engine.eval("f(0) && f(1)").unwrap(), Object::Integer(0));
+    assert_eq!(engine.eval("f(2) || f(3)").unwrap(), Object::Integer(2));
+    assert_eq!(engine.eval("f(0) || f(4) && f(5)").unwrap(), Object::Integer(5));
+    assert_eq!(
+      *calls.borrow(),
+      vec![0, 2, 0, 4, 5].into_iter().map(Object::Integer).collect::<Vec<_>>()
+    );
+  }
+
+  #[test]
+  fn decimal_mode() {
+    let mut engine = Engine::new();
+    assert_eq!(engine.eval("0.1 + 0.2").unwrap().to_string(), "0.3");
+
+    engine.decimal_mode(Some(DecimalContext::default()));
+
+    let testcases = vec![
+      ("0.1 + 0.2", "0.30"),
+      ("0.1 + 0.2 == 0.3", "true"),
+      ("19.99 * 3", "59.97"),
+      ("100.00 / 3", "33.33"),
+      ("1.005 * 1", "1.00"),
+      ("-0.125 * 1", "-0.12"),
+      ("1.05 ^ 2", "1.10"),
+      ("10.00 % 3", "1.00"),
+      ("0.5 + 1/3", "0.83"),
+      ("0.5 + sqrt(4)", "2.5"),
+      ("round(2.675, 2, \"half_up\")", "2.68"),
+      ("round(2.665, 2, \"half_even\")", "2.66"),
+      ("round(-2.661, 2, \"floor\")", "-2.67"),
+      ("round(1250.0, -2, \"half_even\")", "1200"),
+      ("floor(-1.5)", "-2"),
+    ];
+
+    for (input, expected) in testcases {
+      assert_eq!(engine.eval(input).unwrap().to_string(), expected, "{}", input);
+    }
+
+    engine.decimal_mode(Some(DecimalContext { scale: 4, rounding: RoundingMode::Down }));
+    assert_eq!(engine.eval("2.0 / 3").unwrap().to_string(), "0.6666");
+    assert!(matches!(engine.eval("1.5 / 0"), Err(Error::Runtime(..))));
+    assert!(matches!(engine.eval("round(1.5, 0, 42)"), Err(Error::Runtime(..))));
+  }
+
+  #[test]
+  fn currency() {
+    let mut engine = Engine::new();
+    assert!(matches!(engine.eval("100 USD to EUR"), Err(Error::Runtime(..))));
+    assert!(matches!(engine.eval("rates_timestamp()"), Err(Error::Runtime(..))));
+
+    engine.rates(Rates::from_csv("timestamp,2024-05-01\nUSD,1\nEUR,0.92\nGBP,0.8").unwrap());
+
+    let testcases = vec![
+      ("100 USD to EUR", "92 EUR"),
+      ("(10 USD + 5 USD) in GBP", "12 GBP"),
+      ("100 EUR to USD", "108.70 USD"),
+      ("2 * 3 USD - 1 USD", "5 USD"),
+      ("10 USD / 4 USD", "2.5"),
+      ("-(1 GBP)", "-1 GBP"),
+      ("5 USD == 5 USD", "true"),
+      ("5 USD == 5 EUR", "false"),
+      ("100 USD to EUR > 90 EUR", "true"),
+      ("rates_timestamp()", "1714521600"),
+    ];
+
+    for (input, expected) in testcases {
+      assert_eq!(engine.eval(input).unwrap().to_string(), expected, "{}", input);
+    }
+
+    engine.decimal_mode(Some(DecimalContext::default()));
+    assert_eq!(engine.eval("19.99 USD to EUR").unwrap().to_string(), "18.39 EUR");
+
+    for input in ["1 USD + 1 EUR", "1 USD < 1 EUR", "1 USD + 1", "1 USD * 1 USD", "5 to EUR", "1 USD to JPY"] {
+      assert!(matches!(engine.eval(input), Err(Error::Runtime(..))), "{}", input);
+    }
+  }
+}
@@ -0,0 +1,85 @@
+use std::fmt;
+use crate::span::Span;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+  Lex(String, Span),
+  Parse(String, Span),
+  Compile(String, Span),
+  Runtime(String, Span),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+  pub fn message(&self) -> &str {
+    match self {
+      Error::Lex(message, _) |
+      Error::Parse(message, _) |
+      Error::Compile(message, _) |
+      Error::Runtime(message, _) => message,
+    }
+  }
+
+  pub fn span(&self) -> Span {
+    match self {
+      Error::Lex(_, span) |
+      Error::Parse(_, span) |
+      Error::Compile(_, span) |
+      Error::Runtime(_, span) => *span,
+    }
+  }
+
+  /// Renders the error followed by the offending source line, with carets
+  /// under the part of `source` the error's span covers.
+  pub fn render(&self, source: &str) -> String {
+    let span = self.span();
+    let line = source.lines().nth(span.line.saturating_sub(1)).unwrap_or("");
+    let gutter = " ".repeat(span.line.to_string().len());
+
+    let line_start = source[..span.start.min(source.len())].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = line_start + line.len();
+    let start = span.start.clamp(line_start, line_end);
+    let end = span.end.clamp(start, line_end);
+    let width = source[start..end].chars().count().max(1);
+
+    format!(
+      "{}\n{} --> {}:{}\n{} |\n{} | {}\n{} | {}{}",
+      self,
+      gutter, span.line, span.column,
+      gutter,
+      span.line, line,
+      gutter, " ".repeat(span.column.saturating_sub(1)), "^".repeat(width),
+    )
+  }
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Error::Lex(message, _) => write!(f, "lex error: {}", message),
+      Error::Parse(message, _) => write!(f, "parse error: {}", message),
+      Error::Compile(message, _) => write!(f, "compile error: {}", message),
+      Error::Runtime(message, _) => write!(f, "runtime error: {}", message),
+    }
+  }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+  use crate::error::Error;
+  use crate::span::Span;
+
+  #[test]
+  fn render() {
+    let source = "1 +\n  true + 1";
+    let error = Error::Runtime("invalid operands".to_string(), Span::new(6, 14, 2, 3));
+
+    assert_eq!(
+      error.render(source),
+      "runtime error: invalid operands\n  --> 2:3\n  |\n2 |   true + 1\n  |   ^^^^^^^^"
+    );
+  }
+}
@@ -0,0 +1,240 @@
+/// A parsed JSON value. Numbers keep their source text so callers can read
+/// them exactly.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+  Null,
+  Boolean(bool),
+  Number(String),
+  String(String),
+  Array(Vec<Value>),
+  Object(Vec<(String, Value)>),
+}
+
+impl Value {
+  pub fn get(&self, key: &str) -> Option<&Value> {
+    match self {
+      Value::Object(members) => members.iter().find(|(name, _)| name == key).map(|(_, value)| value),
+      _ => None,
+    }
+  }
+}
+
+/// How deeply arrays and objects may nest, since each level recurses.
+const MAX_DEPTH: usize = 128;
+
+struct Parser<'a> {
+  input: &'a [u8],
+  curr: usize,
+  depth: usize,
+}
+
+impl <'a> Parser<'a> {
+  fn peek(&self) -> u8 {
+    self.input.get(self.curr).copied().unwrap_or(b'\0')
+  }
+
+  fn skip_whitespace(&mut self) {
+    while let b' ' | b'\n' | b'\r' | b'\t' = self.peek() {
+      self.curr += 1;
+    }
+  }
+
+  fn error<T>(&self, message: &str) -> Result<T, String> {
+    Err(format!("{} at byte {}", message, self.curr))
+  }
+
+  fn expect(&mut self, byte: u8) -> Result<(), String> {
+    self.skip_whitespace();
+
+    if self.peek() != byte {
+      return self.error(&format!("expected '{}'", byte as char));
+    }
+
+    self.curr += 1;
+    Ok(())
+  }
+
+  fn parse_literal(&mut self, literal: &str, value: Value) -> Result<Value, String> {
+    if !self.input[self.curr..].starts_with(literal.as_bytes()) {
+      return self.error("invalid literal");
+    }
+
+    self.curr += literal.len();
+    Ok(value)
+  }
+
+  fn parse_number(&mut self) -> Result<Value, String> {
+    let start = self.curr;
+
+    while let b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9' = self.peek() {
+      self.curr += 1;
+    }
+
+    let text = std::str::from_utf8(&self.input[start..self.curr]).unwrap_or("");
+
+    match text.parse::<f64>() {
+      Ok(_) => Ok(Value::Number(text.to_string())),
+      Err(_) => self.error("invalid number"),
+    }
+  }
+
+  fn parse_string(&mut self) -> Result<String, String> {
+    self.expect(b'"')?;
+
+    let mut bytes = Vec::new();
+
+    loop {
+      match self.peek() {
+        b'"' => break,
+        b'\0' if self.curr >= self.input.len() => return self.error("unterminated string"),
+        b'\\' => {
+          self.curr += 1;
+
+          let escaped = match self.peek() {
+            b'n' => b'\n',
+            b't' => b'\t',
+            b'r' => b'\r',
+            b'b' => 0x08,
+            b'f' => 0x0c,
+            b'u' => {
+              let hex = std::str::from_utf8(self.input.get(self.curr + 1..self.curr + 5).unwrap_or(&[])).unwrap_or("");
+              let ch = u32::from_str_radix(hex, 16).ok().and_then(char::from_u32);
+
+              match ch {
+                Some(ch) => bytes.extend_from_slice(ch.to_string().as_bytes()),
+                None => return self.error("invalid unicode escape"),
+              }
+
+              self.curr += 5;
+              continue;
+            }
+            byte => byte,
+          };
+
+          bytes.push(escaped);
+        }
+        byte => bytes.push(byte),
+      }
+
+      self.curr += 1;
+    }
+
+    self.curr += 1;
+
+    String::from_utf8(bytes).or_else(|_| self.error("invalid UTF-8 in string"))
+  }
+
+  fn parse_value(&mut self) -> Result<Value, String> {
+    if self.depth == MAX_DEPTH {
+      return self.error("nesting too deep");
+    }
+
+    self.depth += 1;
+    let value = self.parse_inner();
+    self.depth -= 1;
+
+    value
+  }
+
+  fn parse_inner(&mut self) -> Result<Value, String> {
+    self.skip_whitespace();
+
+    match self.peek() {
+      b'{' => {
+        self.curr += 1;
+        let mut members = Vec::new();
+
+        self.skip_whitespace();
+
+        if self.peek() == b'}' {
+          self.curr += 1;
+          return Ok(Value::Object(members));
+        }
+
+        loop {
+          self.skip_whitespace();
+          let key = self.parse_string()?;
+          self.expect(b':')?;
+          members.push((key, self.parse_value()?));
+          self.skip_whitespace();
+
+          match self.peek() {
+            b',' => self.curr += 1,
+            b'}' => break,
+            _ => return self.error("expected ',' or '}'"),
+          }
+        }
+
+        self.curr += 1;
+        Ok(Value::Object(members))
+      }
+      b'[' => {
+        self.curr += 1;
+        let mut elements = Vec::new();
+
+        self.skip_whitespace();
+
+        if self.peek() == b']' {
+          self.curr += 1;
+          return Ok(Value::Array(elements));
+        }
+
+        loop {
+          elements.push(self.parse_value()?);
+          self.skip_whitespace();
+
+          match self.peek() {
+            b',' => self.curr += 1,
+            b']' => break,
+            _ => return self.error("expected ',' or ']'"),
+          }
+        }
+
+        self.curr += 1;
+        Ok(Value::Array(elements))
+      }
+      b'"' => Ok(Value::String(self.parse_string()?)),
+      b't' => self.parse_literal("true", Value::Boolean(true)),
+      b'f' => self.parse_literal("false", Value::Boolean(false)),
+      b'n' => self.parse_literal("null", Value::Null),
+      b'-' | b'0'..=b'9' => self.parse_number(),
+      _ => self.error("expected a value"),
+    }
+  }
+}
+
+pub fn parse(input: &str) -> Result<Value, String> {
+  let mut parser = Parser {
+    input: input.as_bytes(),
+    curr: 0,
+    depth: 0,
+  };
+
+  let value = parser.parse_value()?;
+  parser.skip_whitespace();
+
+  if parser.curr < parser.input.len() {
+    return parser.error("unexpected trailing characters");
+  }
+
+  Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::json::{self, Value};
+
+  #[test]
+  fn json() {
+    let value = json::parse(r#" {"base": "USD", "rates": {"EUR": 0.92, "JPY": 151}, "ok": [true, null], "s": "a\"é"} "#).unwrap();
+
+    assert_eq!(value.get("base"), Some(&Value::String("USD".to_string())));
+    assert_eq!(value.get("rates").and_then(|rates| rates.get("EUR")), Some(&Value::Number("0.92".to_string())));
+    assert_eq!(value.get("ok"), Some(&Value::Array(vec![Value::Boolean(true), Value::Null])));
+    assert_eq!(value.get("s"), Some(&Value::String("a\"é".to_string())));
+
+    for input in ["", "{", "{\"a\" 1}", "[1,]", "tru", "{} x", "\"abc"] {
+      assert!(json::parse(input).is_err(), "{}", input);
+    }
+  }
+}
@@ -1,9 +1,56 @@
+use crate::error::{Error, Result};
+use crate::span::Span;
 use crate::token::{Token, TokenKind};
 
+/// Decodes the escapes in the contents of a string literal: `\n`, `\t`,
+/// `\r`, `\0`, `\\`, `\"` and `\u{...}`. Returns `None` for an invalid escape.
+pub fn unescape(contents: &str) -> Option<String> {
+  let mut result = String::with_capacity(contents.len());
+  let mut chars = contents.chars();
+
+  while let Some(ch) = chars.next() {
+    if ch != '\\' {
+      result.push(ch);
+      continue;
+    }
+
+    let escaped = match chars.next()? {
+      'n' => '\n',
+      't' => '\t',
+      'r' => '\r',
+      '0' => '\0',
+      '\\' => '\\',
+      '"' => '"',
+      'u' => {
+        let rest = chars.as_str().strip_prefix('{')?;
+        let (hex, rest) = rest.split_once('}')?;
+
+        if hex.is_empty() || hex.len() > 6 {
+          return None;
+        }
+
+        chars = rest.chars();
+        char::from_u32(u32::from_str_radix(hex, 16).ok()?)?
+      }
+      _ => return None,
+    };
+
+    result.push(escaped);
+  }
+
+  Some(result)
+}
+
 pub struct Lexer<'a> {
   input: &'a str,
   curr: usize,
   next: usize,
+  line: usize,
+  line_start: usize,
+  decimal: bool,
+  /// How many brackets are open; line breaks inside them are whitespace.
+  depth: usize,
+  last: TokenKind,
 }
 
 impl <'a> Lexer<'a> {
@@ -12,9 +59,20 @@ impl <'a> Lexer<'a> {
       input,
       curr: 0,
       next: 1,
+      line: 1,
+      line_start: 0,
+      decimal: false,
+      depth: 0,
+      last: TokenKind::Eof,
     }
   }
 
+  /// Lexes fractional literals such as `0.1` as `Decimal` tokens.
+  pub fn decimal_literals(mut self, enabled: bool) -> Self {
+    self.decimal = enabled;
+    self
+  }
+
   fn is_at_end(&self) -> bool {
     self.curr >= self.input.len()
   }
@@ -39,78 +97,211 @@ impl <'a> Lexer<'a> {
     let ch = self.peek();
     self.curr = self.next;
     self.next += 1;
+
+    if ch == b'\n' {
+      self.line += 1;
+      self.line_start = self.curr;
+    }
+
     ch
   }
 
+  fn span(&self, start: usize, end: usize) -> Span {
+    let column = self.input[self.line_start..start].chars().count() + 1;
+    Span::new(start, end, self.line, column)
+  }
+
+  /// Whether a line break here separates statements: outside brackets,
+  /// after a token that can end an expression, so `1 +` continues a line.
+  fn is_separator_newline(&self) -> bool {
+    self.depth == 0 && matches!(
+      self.last,
+      TokenKind::Number
+        | TokenKind::Decimal
+        | TokenKind::Imaginary
+        | TokenKind::Boolean
+        | TokenKind::String
+        | TokenKind::Identifier
+        | TokenKind::RightParen
+        | TokenKind::RightBracket
+        | TokenKind::RightBrace
+    )
+  }
+
   fn skip_whitespace(&mut self) {
-    loop {
-      match self.peek() {
-        b' ' | b'\n' | b'\r' | b'\t' => {
-          self.advance();
-        }
-        _ => break,
+    while let b' ' | b'\n' | b'\r' | b'\t' = self.peek() {
+      if self.peek() == b'\n' && self.is_separator_newline() {
+        break;
       }
+
+      self.advance();
     }
   }
 
-  fn read_number(&mut self) -> Token<'a> {
-    let start = self.curr;
+  fn number_error<T>(&self, start: usize, message: String) -> Result<T> {
+    Err(Error::Lex(message, self.span(start, self.curr)))
+  }
+
+  /// Reads digits of the given radix, allowing `_` separators between them,
+  /// and returns how many digits were read.
+  fn read_digits(&mut self, start: usize, radix: u32) -> Result<usize> {
+    let mut count = 0;
+    let mut separator = false;
 
     loop {
-      match self.peek() {
-        b'0'..=b'9' => {
-          self.advance();
-        }
-        _ => {
+      let ch = self.peek();
+
+      if (ch as char).is_digit(radix) {
+        count += 1;
+        separator = false;
+      } else if ch == b'_' {
+        if count == 0 || separator {
           break;
         }
+        separator = true;
+      } else {
+        break;
+      }
+
+      self.advance();
+    }
+
+    if separator || self.peek() == b'_' {
+      return self.number_error(start, "digit separator '_' must be between digits".to_string());
+    }
+
+    Ok(count)
+  }
+
+  fn read_radix_number(&mut self, start: usize, radix: u32, name: &str) -> Result<Token<'a>> {
+    self.advance();
+    self.advance();
+
+    if self.read_digits(start, radix)? == 0 {
+      let prefix = &self.input[start..self.curr];
+      return self.number_error(start, format!("expected {} digits after '{}'", name, prefix));
+    }
+
+    if self.peek().is_ascii_alphanumeric() {
+      self.advance();
+      return self.number_error(start, format!("invalid digit '{}' in {} literal", self.input.as_bytes()[self.curr - 1] as char, name));
+    }
+
+    let slice = &self.input[start..self.curr];
+
+    Ok(Token::new(TokenKind::Number, slice, self.span(start, self.curr)))
+  }
+
+  fn read_number(&mut self) -> Result<Token<'a>> {
+    let start = self.curr;
+
+    if self.peek() == b'0' {
+      match self.next_peek() {
+        b'x' | b'X' => return self.read_radix_number(start, 16, "hexadecimal"),
+        b'b' | b'B' => return self.read_radix_number(start, 2, "binary"),
+        b'o' | b'O' => return self.read_radix_number(start, 8, "octal"),
+        _ => {}
       }
     }
 
-    if self.peek() == b'.' {
+    self.read_digits(start, 10)?;
+    let mut fractional = false;
+
+    if self.peek() == b'.' && self.next_peek() != b'.' {
       self.advance();
+      self.read_digits(start, 10)?;
+      fractional = true;
+    }
 
-      loop {
-        match self.peek() {
-          b'0'..=b'9' => {
-            self.advance();
-          }
-          _ => {
-            break;
-          }
-        }
+    if let b'e' | b'E' = self.peek() {
+      self.advance();
+      fractional = true;
+
+      if let b'+' | b'-' = self.peek() {
+        self.advance();
+      }
+
+      if self.read_digits(start, 10)? == 0 {
+        let slice = &self.input[start..self.curr];
+        return self.number_error(start, format!("expected exponent digits in '{}'", slice));
       }
     }
 
+    let kind = match self.peek() {
+      b'i' | b'j' if !self.next_peek().is_ascii_alphanumeric() && self.next_peek() != b'_' => {
+        self.advance();
+        TokenKind::Imaginary
+      }
+      _ if self.decimal && fractional => TokenKind::Decimal,
+      _ => TokenKind::Number,
+    };
+
     let slice = &self.input[start..self.curr];
 
-    Token::new(TokenKind::Number, slice)
+    Ok(Token::new(kind, slice, self.span(start, self.curr)))
   }
 
-  fn read_identifier(&mut self) -> Token<'a> {
+  fn read_string(&mut self) -> Result<Token<'a>> {
     let start = self.curr;
+    // Strings may span lines, so the span starts where the quote does.
+    let opening = self.span(start, start + 1);
+    let span = |end| Span::new(start, end, opening.line, opening.column);
+
+    self.advance();
 
     loop {
+      if self.is_at_end() {
+        return Err(Error::Lex("unterminated string".to_string(), span(self.curr)));
+      }
+
       match self.peek() {
-        b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'_' => {
+        b'"' => break,
+        b'\\' if self.next < self.input.len() => {
           self.advance();
         }
-        _ => {
-          break;
-        }
+        _ => {}
       }
+
+      self.advance();
+    }
+
+    self.advance();
+
+    let slice = &self.input[start..self.curr];
+
+    if unescape(&slice[1..slice.len() - 1]).is_none() {
+      return Err(Error::Lex(format!("invalid escape sequence in {}", slice), span(self.curr)));
+    }
+
+    Ok(Token::new(TokenKind::String, slice, span(self.curr)))
+  }
+
+  fn read_identifier(&mut self) -> Token<'a> {
+    let start = self.curr;
+
+    while self.peek().is_ascii_alphanumeric() || self.peek() == b'_' {
+      self.advance();
     }
 
     let slice = &self.input[start..self.curr];
     let kind = match slice {
       "true" | "false" => TokenKind::Boolean,
+      "let" => TokenKind::Let,
+      "for" => TokenKind::For,
+      "while" => TokenKind::While,
+      "if" => TokenKind::If,
+      "then" => TokenKind::Then,
+      "else" => TokenKind::Else,
+      "xor" => TokenKind::Xor,
+      "in" => TokenKind::In,
+      "to" => TokenKind::To,
       _ => TokenKind::Identifier,
     };
 
-    Token::new(kind, slice)
+    Token::new(kind, slice, self.span(start, self.curr))
   }
 
-  fn token(&mut self) -> Token<'a> {
+  fn token(&mut self) -> Result<Token<'a>> {
     self.skip_whitespace();
 
     let curr = self.curr;
@@ -119,28 +310,52 @@ impl <'a> Lexer<'a> {
       b'0'..=b'9' => {
         return self.read_number();
       }
+      b'"' => {
+        return self.read_string();
+      }
       b'>' => {
-        if self.next_peek() == b'=' {
-          self.advance();
-          TokenKind::GreaterEqual
-        } else {
-          TokenKind::Greater
+        match self.next_peek() {
+          b'=' => {
+            self.advance();
+            TokenKind::GreaterEqual
+          }
+          b'>' => {
+            self.advance();
+
+            if self.next_peek() == b'>' {
+              self.advance();
+              TokenKind::GreaterGreaterGreater
+            } else {
+              TokenKind::GreaterGreater
+            }
+          }
+          _ => TokenKind::Greater,
         }
       }
       b'<' => {
-        if self.next_peek() == b'=' {
-          self.advance();
-          TokenKind::LessEqual
-        } else {
-          TokenKind::Less
+        match self.next_peek() {
+          b'=' => {
+            self.advance();
+            TokenKind::LessEqual
+          }
+          b'<' => {
+            self.advance();
+            TokenKind::LessLess
+          }
+          _ => TokenKind::Less,
         }
       }
       b'=' => {
-        if self.next_peek() == b'=' {
-          self.advance();
-          TokenKind::EqualEqual
-        } else {
-          TokenKind::Equal
+        match self.next_peek() {
+          b'=' => {
+            self.advance();
+            TokenKind::EqualEqual
+          }
+          b'>' => {
+            self.advance();
+            TokenKind::EqualGreater
+          }
+          _ => TokenKind::Equal,
         }
       }
       b'!' => {
@@ -170,6 +385,28 @@ impl <'a> Lexer<'a> {
       b',' => {
         TokenKind::Comma
       }
+      b'?' => {
+        TokenKind::Question
+      }
+      b':' => {
+        TokenKind::Colon
+      }
+      b';' => {
+        TokenKind::Semicolon
+      }
+      b'.' if self.next_peek() == b'.' => {
+        self.advance();
+
+        if self.next_peek() == b'=' {
+          self.advance();
+          TokenKind::DotDotEqual
+        } else {
+          TokenKind::DotDot
+        }
+      }
+      b'\n' => {
+        TokenKind::Newline
+      }
       b'+' => {
         TokenKind::Plus
       }
@@ -177,7 +414,18 @@ impl <'a> Lexer<'a> {
         TokenKind::Minus
       }
       b'*' => {
-        TokenKind::Star
+        if self.next_peek() == b'*' {
+          self.advance();
+          TokenKind::StarStar
+        } else {
+          TokenKind::Star
+        }
+      }
+      b'^' => {
+        TokenKind::Caret
+      }
+      b'~' => {
+        TokenKind::Tilde
       }
       b'/' => {
         TokenKind::Slash
@@ -191,37 +439,59 @@ impl <'a> Lexer<'a> {
       b')' => {
         TokenKind::RightParen
       }
+      b'[' => {
+        TokenKind::LeftBracket
+      }
+      b']' => {
+        TokenKind::RightBracket
+      }
+      b'{' => {
+        TokenKind::LeftBrace
+      }
+      b'}' => {
+        TokenKind::RightBrace
+      }
       b'a' ..= b'z' | b'A' ..= b'Z' => {
-        return self.read_identifier();
+        return Ok(self.read_identifier());
       }
       b'\0' => {
-        return Token::new(TokenKind::Eof, "");
+        return Ok(Token::new(TokenKind::Eof, "", self.span(curr, curr)));
       }
       _ => {
-        panic!("Unexpected character: {}", self.peek() as char)
+        let ch = self.input[curr..].chars().next().unwrap_or_default();
+        let span = self.span(curr, curr + ch.len_utf8());
+        return Err(Error::Lex(format!("unexpected character '{}'", ch), span));
       },
     };
 
     let slice = &self.input[curr..self.next];
+    let span = self.span(curr, self.next);
     self.advance();
-    Token::new(kind, slice)
+    Ok(Token::new(kind, slice, span))
   }
 
-  pub fn lex(&mut self) -> Vec<Token> {
+  pub fn lex(&mut self) -> Result<Vec<Token<'a>>> {
     let mut tokens = Vec::new();
 
     loop {
-      let token = self.token();
+      let token = self.token()?;
+      let is_eof = token.kind == TokenKind::Eof;
+
+      match token.kind {
+        TokenKind::LeftParen | TokenKind::LeftBracket => self.depth += 1,
+        TokenKind::RightParen | TokenKind::RightBracket => self.depth = self.depth.saturating_sub(1),
+        _ => {}
+      }
+
+      self.last = token.kind.clone();
       tokens.push(token);
 
-      if let Some(last_token) = tokens.last() {
-        if last_token.kind == TokenKind::Eof {
-          break;
-        }
+      if is_eof {
+        break;
       }
     }
 
-    return tokens;
+    Ok(tokens)
   }
 }
 
@@ -234,4 +504,70 @@ mod tests {
 
     println!("{:#?}", tokens);
   }
+
+  #[test]
+  fn shifts() {
+    use crate::token::TokenKind;
+
+    let mut lexer = super::Lexer::new("1 << 2 >> 3 >>> 4 > 5 >= 6 < 7");
+    let kinds: Vec<TokenKind> = lexer.lex().unwrap().iter().map(|token| token.kind.clone()).collect();
+
+    assert_eq!(kinds[1], TokenKind::LessLess);
+    assert_eq!(kinds[3], TokenKind::GreaterGreater);
+    assert_eq!(kinds[5], TokenKind::GreaterGreaterGreater);
+    assert_eq!(kinds[7], TokenKind::Greater);
+    assert_eq!(kinds[9], TokenKind::GreaterEqual);
+    assert_eq!(kinds[11], TokenKind::Less);
+  }
+
+  #[test]
+  fn spans() {
+    let mut lexer = super::Lexer::new("1 +\n  20");
+    let tokens = lexer.lex().unwrap();
+    let span = tokens[2].span;
+
+    assert_eq!((span.start, span.end, span.line, span.column), (6, 8, 2, 3));
+  }
+
+  #[test]
+  fn number_literals() {
+    let valid = vec!["0xFF", "0b1010", "0o755", "1_000_000", "1e-9", "2.5E+3", "3.", "3i", "2.5j", "1_0e2i"];
+
+    for input in valid {
+      let mut lexer = super::Lexer::new(input);
+      let tokens = lexer.lex();
+
+      assert!(matches!(tokens.as_deref(), Ok([_, _])), "{}", input);
+    }
+
+    let invalid = vec!["0x", "0b102", "0o", "0xFG", "1e", "1e+", "1_", "1__0", "1_.5", "0x_1"];
+
+    for input in invalid {
+      let mut lexer = super::Lexer::new(input);
+
+      assert!(matches!(lexer.lex(), Err(crate::error::Error::Lex(..))), "{}", input);
+    }
+  }
+
+  #[test]
+  fn strings() {
+    let mut lexer = super::Lexer::new(r#""a\"b" + "\u{e9}\n""#);
+    let tokens = lexer.lex().unwrap();
+
+    assert_eq!(tokens[0].slice, r#""a\"b""#);
+    assert_eq!(super::unescape(&tokens[2].slice[1..tokens[2].slice.len() - 1]).as_deref(), Some("é\n"));
+
+    for input in [r#""abc"#, r#""a\q""#, r#""\u{110000}""#, r#""\"#] {
+      let mut lexer = super::Lexer::new(input);
+
+      assert!(matches!(lexer.lex(), Err(crate::error::Error::Lex(..))), "{}", input);
+    }
+  }
+
+  #[test]
+  fn unexpected_character() {
+    let mut lexer = super::Lexer::new("1 + @");
+
+    assert!(matches!(lexer.lex(), Err(crate::error::Error::Lex(..))));
+  }
 }
\ No newline at end of file
@@ -1,9 +1,23 @@
 mod ast;
+pub mod bigint;
 mod bytecode;
 pub mod compiler;
+pub mod complex;
+pub mod currency;
+pub mod decimal;
+pub mod engine;
+pub mod error;
+mod json;
 pub mod lexer;
+pub mod matrix;
 mod token;
 mod opcode;
-mod object;
+pub mod object;
+pub mod native;
+mod number;
 pub mod parser;
+pub mod range;
+pub mod rational;
+pub mod span;
+pub mod units;
 pub mod vm;
@@ -1,25 +1,61 @@
-use calculator::compiler::Compiler;
-use calculator::lexer::Lexer;
-use calculator::parser::Parser;
-use calculator::vm::VM;
+use calculator::currency::Rates;
+use calculator::decimal::DecimalContext;
+use calculator::engine::Engine;
+use calculator::object::Object;
+
+fn main() -> std::result::Result<(), std::io::Error> {
+  let mut engine = Engine::new();
+
+  // `--decimal` or `--decimal=SCALE` reads fractional literals as decimals.
+  for arg in std::env::args().skip(1) {
+    if let Some(scale) = arg.strip_prefix("--decimal") {
+      let mut context = DecimalContext::default();
+
+      if let Some(scale) = scale.strip_prefix('=') {
+        context.scale = match scale.parse() {
+          Ok(scale) if scale <= calculator::decimal::MAX_SCALE => scale,
+          _ => {
+            eprintln!("invalid decimal scale '{}'", scale);
+            std::process::exit(2);
+          }
+        };
+      }
+
+      engine.decimal_mode(Some(context));
+    }
+
+    // `--rates=PATH` loads exchange rates from a JSON or CSV file.
+    if let Some(path) = arg.strip_prefix("--rates=") {
+      match Rates::load(std::path::Path::new(path)) {
+        Ok(rates) => {
+          engine.rates(rates);
+        }
+        Err(message) => {
+          eprintln!("{}", message);
+          std::process::exit(2);
+        }
+      }
+    }
+  }
 
-fn main() -> Result<(), std::io::Error> {
   loop {
     let mut buffer = String::new();
-    std::io::stdin().read_line(&mut buffer)?;
 
-    if buffer.trim().is_empty() {
-      continue;
+    if std::io::stdin().read_line(&mut buffer)? == 0 {
+      break;
     }
 
-    let mut lexer = Lexer::new(&buffer);
-    let tokens = lexer.lex();
-    let program = Parser::new(tokens).parse();
-    let bytecode = Compiler::new().compile(&program);
-    let mut vm = VM::default();
+    // Without the newline an error at the end of input points at this line.
+    let source = buffer.trim_end_matches(['\n', '\r']);
+
+    if source.trim().is_empty() {
+      continue;
+    }
 
-    if let Some(res) = vm.run(bytecode) {
-      println!("{}", res);
+    match engine.eval(source) {
+      Ok(Object::Unit) => {}
+      Ok(res) => println!("{}", res),
+      Err(err) => eprintln!("{}", err.render(source)),
     }
   }
 
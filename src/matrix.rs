@@ -0,0 +1,468 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::rc::Rc;
+use crate::ast::BinaryOperator;
+use crate::decimal::DecimalContext;
+use crate::number;
+use crate::object::Object;
+
+/// A rectangular, non-empty grid of real numbers, stored row by row.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Matrix {
+  rows: usize,
+  cols: usize,
+  data: Vec<Object>,
+}
+
+/// Most element multiplications one matrix operation may take, so that
+/// products, powers and eliminations of large matrices are an error rather
+/// than running for minutes.
+const MAX_MATRIX_WORK: u64 = 20_000_000;
+
+fn check_work(operation: &str, work: u64) -> Result<(), String> {
+  match work {
+    work if work > MAX_MATRIX_WORK => Err(format!("{} would take about {} multiplications, more than {}", operation, work, MAX_MATRIX_WORK)),
+    _ => Ok(()),
+  }
+}
+
+/// Treats floats within rounding noise of zero as zero when choosing pivots.
+fn is_negligible(value: &Object) -> bool {
+  match value {
+    Object::Number(n) => n.abs() < 1e-12,
+    _ => number::compare(value, &Object::Integer(0)) == Some(Ordering::Equal),
+  }
+}
+
+fn magnitude(value: &Object) -> f64 {
+  value.to_f64().unwrap_or_default().abs()
+}
+
+impl Matrix {
+  pub fn new(rows: usize, cols: usize, data: Vec<Object>) -> Self {
+    debug_assert_eq!(rows * cols, data.len());
+
+    Self {
+      rows,
+      cols,
+      data,
+    }
+  }
+
+  /// Builds a matrix from a list of equally long, non-empty lists of real
+  /// numbers, or returns `None` if the values do not form one.
+  pub fn from_rows(rows: &[Object]) -> Option<Self> {
+    let cols = match rows.first()? {
+      Object::List(row) if !row.is_empty() => row.len(),
+      _ => return None,
+    };
+
+    let mut data = Vec::with_capacity(rows.len() * cols);
+
+    for row in rows {
+      match row {
+        Object::List(row) if row.len() == cols && row.iter().all(Object::is_real) => data.extend(row.iter().cloned()),
+        _ => return None,
+      }
+    }
+
+    Some(Matrix::new(rows.len(), cols, data))
+  }
+
+  pub fn identity(n: usize) -> Self {
+    let data = (0..n * n).map(|index| Object::Integer((index % (n + 1) == 0) as i64)).collect();
+    Matrix::new(n, n, data)
+  }
+
+  pub fn rows(&self) -> usize {
+    self.rows
+  }
+
+  pub fn cols(&self) -> usize {
+    self.cols
+  }
+
+  pub fn is_square(&self) -> bool {
+    self.rows == self.cols
+  }
+
+  pub fn get(&self, row: usize, col: usize) -> &Object {
+    &self.data[row * self.cols + col]
+  }
+
+  fn set(&mut self, row: usize, col: usize, value: Object) {
+    self.data[row * self.cols + col] = value;
+  }
+
+  pub fn row(&self, row: usize) -> Object {
+    Object::List(Rc::new(self.data[row * self.cols..(row + 1) * self.cols].to_vec()))
+  }
+
+  /// The rows as a list of lists.
+  pub fn to_list(&self) -> Vec<Object> {
+    (0..self.rows).map(|row| self.row(row)).collect()
+  }
+
+  /// Describes the shape, such as `2x3`, for error messages.
+  pub fn shape(&self) -> String {
+    format!("{}x{}", self.rows, self.cols)
+  }
+
+  pub fn transpose(&self) -> Matrix {
+    let data = (0..self.cols)
+      .flat_map(|col| (0..self.rows).map(move |row| (row, col)))
+      .map(|(row, col)| self.get(row, col).clone())
+      .collect();
+
+    Matrix::new(self.cols, self.rows, data)
+  }
+
+  pub fn map<F>(&self, mut f: F) -> Result<Matrix, String>
+  where
+    F: FnMut(&Object) -> Result<Object, String>,
+  {
+    let data = self.data.iter().map(&mut f).collect::<Result<_, _>>()?;
+    Ok(Matrix::new(self.rows, self.cols, data))
+  }
+
+  pub fn matmul(&self, other: &Matrix, context: &DecimalContext) -> Result<Matrix, String> {
+    if self.cols != other.rows {
+      return Err(format!("dimension mismatch for '*': {} and {}", self.shape(), other.shape()));
+    }
+
+    check_work("the matrix product", (self.rows as u64).saturating_mul(self.cols as u64).saturating_mul(other.cols as u64))?;
+
+    let mut data = Vec::with_capacity(self.rows * other.cols);
+
+    for row in 0..self.rows {
+      for col in 0..other.cols {
+        let mut total = Object::Integer(0);
+
+        for k in 0..self.cols {
+          let product = number::arithmetic(&BinaryOperator::Multiply, self.get(row, k), other.get(k, col), context)?;
+          total = number::arithmetic(&BinaryOperator::Add, &total, &product, context)?;
+        }
+
+        data.push(total);
+      }
+    }
+
+    Ok(Matrix::new(self.rows, other.cols, data))
+  }
+
+  pub fn trace(&self, context: &DecimalContext) -> Result<Object, String> {
+    self.require_square("trace")?;
+
+    (0..self.rows).try_fold(Object::Integer(0), |total, index| {
+      number::arithmetic(&BinaryOperator::Add, &total, self.get(index, index), context)
+    })
+  }
+
+  fn require_square(&self, operation: &str) -> Result<(), String> {
+    match self.is_square() {
+      true => Ok(()),
+      false => Err(format!("{} needs a square matrix, got {}", operation, self.shape())),
+    }
+  }
+
+  /// Gauss-Jordan elimination over the first `pivot_cols` columns, with
+  /// partial pivoting. Leaves the matrix in reduced row echelon form and
+  /// returns its rank and the determinant of the leading square block.
+  fn row_reduce(&mut self, pivot_cols: usize, context: &DecimalContext) -> Result<(usize, Object), String> {
+    check_work("elimination", (self.rows as u64).saturating_mul(self.rows as u64).saturating_mul(self.cols as u64))?;
+
+    let arithmetic = |op, left: &Object, right: &Object| number::arithmetic(&op, left, right, context);
+    let mut det = Object::Integer(1);
+    let mut rank = 0;
+
+    for col in 0..pivot_cols {
+      let pivot = (rank..self.rows)
+        .filter(|row| !is_negligible(self.get(*row, col)))
+        .max_by(|a, b| magnitude(self.get(*a, col)).total_cmp(&magnitude(self.get(*b, col))));
+
+      let Some(pivot) = pivot else {
+        det = Object::Integer(0);
+        continue;
+      };
+
+      if pivot != rank {
+        for c in 0..self.cols {
+          self.data.swap(pivot * self.cols + c, rank * self.cols + c);
+        }
+
+        det = number::negate(&det).unwrap_or(det);
+      }
+
+      let value = self.get(rank, col).clone();
+      det = arithmetic(BinaryOperator::Multiply, &det, &value)?;
+
+      for c in 0..self.cols {
+        let scaled = arithmetic(BinaryOperator::Divide, self.get(rank, c), &value)?;
+        self.set(rank, c, scaled);
+      }
+
+      for row in (0..self.rows).filter(|row| *row != rank) {
+        let factor = self.get(row, col).clone();
+
+        if is_negligible(&factor) {
+          continue;
+        }
+
+        for c in 0..self.cols {
+          let product = arithmetic(BinaryOperator::Multiply, &factor, self.get(rank, c))?;
+          let difference = arithmetic(BinaryOperator::Subtract, self.get(row, c), &product)?;
+          self.set(row, c, difference);
+        }
+      }
+
+      rank += 1;
+
+      if rank == self.rows {
+        break;
+      }
+    }
+
+    Ok((rank, det))
+  }
+
+  pub fn det(&self, context: &DecimalContext) -> Result<Object, String> {
+    self.require_square("det")?;
+
+    let (rank, det) = self.clone().row_reduce(self.cols, context)?;
+    Ok(if rank < self.rows { Object::Integer(0) } else { det })
+  }
+
+  pub fn rank(&self, context: &DecimalContext) -> Result<usize, String> {
+    Ok(self.clone().row_reduce(self.cols, context)?.0)
+  }
+
+  /// Solves `self * x = rhs` for a square, non-singular matrix by reducing
+  /// the augmented matrix `[self | rhs]`.
+  pub fn solve(&self, rhs: &Matrix, context: &DecimalContext) -> Result<Matrix, String> {
+    self.require_square("solve")?;
+
+    if rhs.rows != self.rows {
+      return Err(format!("dimension mismatch: {} matrix and {} right-hand side", self.shape(), rhs.shape()));
+    }
+
+    let cols = self.cols + rhs.cols;
+    let data = (0..self.rows)
+      .flat_map(|row| (0..cols).map(move |col| (row, col)))
+      .map(|(row, col)| if col < self.cols { self.get(row, col).clone() } else { rhs.get(row, col - self.cols).clone() })
+      .collect();
+
+    let mut augmented = Matrix::new(self.rows, cols, data);
+
+    if augmented.row_reduce(self.cols, context)?.0 < self.rows {
+      return Err("matrix is singular".to_string());
+    }
+
+    let data = (0..self.rows)
+      .flat_map(|row| (self.cols..cols).map(move |col| (row, col)))
+      .map(|(row, col)| augmented.get(row, col).clone())
+      .collect();
+
+    Ok(Matrix::new(self.rows, rhs.cols, data))
+  }
+
+  pub fn inverse(&self, context: &DecimalContext) -> Result<Matrix, String> {
+    self.solve(&Matrix::identity(self.rows), context)
+  }
+
+  /// Raises a square matrix to an integer power by repeated squaring; a
+  /// negative power inverts it first.
+  pub fn pow(&self, exp: i64, context: &DecimalContext) -> Result<Matrix, String> {
+    self.require_square("a matrix power")?;
+
+    // Repeated squaring takes up to two products per bit of the exponent.
+    let size = self.rows as u64;
+    let products = 2 * (u64::BITS - exp.unsigned_abs().leading_zeros()) as u64;
+    check_work("the matrix power", size.saturating_pow(3).saturating_mul(products))?;
+
+    let mut base = if exp < 0 { self.inverse(context)? } else { self.clone() };
+    let mut result = Matrix::identity(self.rows);
+    let mut exp = exp.unsigned_abs();
+
+    while exp > 0 {
+      if exp & 1 == 1 {
+        result = result.matmul(&base, context)?;
+      }
+
+      exp >>= 1;
+
+      if exp > 0 {
+        base = base.matmul(&base, context)?;
+      }
+    }
+
+    Ok(result)
+  }
+
+  /// Eigenvalues of a symmetric matrix in ascending order, by the cyclic
+  /// Jacobi method.
+  pub fn symmetric_eigenvalues(&self) -> Result<Vec<f64>, String> {
+    self.require_square("eig")?;
+
+    let n = self.rows;
+    let mut a: Vec<f64> = self.data.iter().map(|value| value.to_f64().unwrap_or(f64::NAN)).collect();
+
+    for row in 0..n {
+      for col in 0..row {
+        let (upper, lower) = (a[col * n + row], a[row * n + col]);
+
+        if (upper - lower).abs() > 1e-9 * upper.abs().max(lower.abs()).max(1.0) {
+          return Err("eig only supports symmetric matrices".to_string());
+        }
+      }
+    }
+
+    for _ in 0..100 {
+      let off_diagonal: f64 = (0..n * n).filter(|index| index / n != index % n).map(|index| a[index] * a[index]).sum();
+
+      if off_diagonal < 1e-30 {
+        break;
+      }
+
+      for p in 0..n {
+        for q in p + 1..n {
+          if a[p * n + q] == 0.0 {
+            continue;
+          }
+
+          // Rotate rows and columns p and q so that a[p][q] becomes zero.
+          let theta = (a[q * n + q] - a[p * n + p]) / (2.0 * a[p * n + q]);
+          let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+          let t = if theta == 0.0 { 1.0 } else { t };
+          let c = 1.0 / (t * t + 1.0).sqrt();
+          let s = t * c;
+
+          for k in 0..n {
+            let (akp, akq) = (a[k * n + p], a[k * n + q]);
+            a[k * n + p] = c * akp - s * akq;
+            a[k * n + q] = s * akp + c * akq;
+          }
+
+          for k in 0..n {
+            let (apk, aqk) = (a[p * n + k], a[q * n + k]);
+            a[p * n + k] = c * apk - s * aqk;
+            a[q * n + k] = s * apk + c * aqk;
+          }
+        }
+      }
+    }
+
+    let mut values: Vec<f64> = (0..n).map(|index| a[index * n + index]).collect();
+    values.sort_by(f64::total_cmp);
+
+    Ok(values)
+  }
+}
+
+impl fmt::Display for Matrix {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    fmt::Display::fmt(&Object::List(Rc::new(self.to_list())), f)
+  }
+}
+
+/// Builds a list of rows into a matrix where it forms one.
+pub fn from_list(items: Vec<Object>) -> Object {
+  match Matrix::from_rows(&items) {
+    Some(matrix) => Object::Matrix(Rc::new(matrix)),
+    None => Object::List(Rc::new(items)),
+  }
+}
+
+fn vector(items: &[Object]) -> Option<Matrix> {
+  match items.iter().all(Object::is_real) && !items.is_empty() {
+    true => Some(Matrix::new(items.len(), 1, items.to_vec())),
+    false => None,
+  }
+}
+
+/// Arithmetic where either operand is a matrix. `*` between matrices, or a
+/// matrix and a list used as a vector, is the matrix product; with a scalar,
+/// and for `+` and `-` between matrices of one shape, it works element-wise.
+pub fn arithmetic(op: &BinaryOperator, left: &Object, right: &Object, context: &DecimalContext) -> Result<Object, String> {
+  let elementwise = |left: &Object, right: &Object| number::arithmetic(op, left, right, context);
+  let invalid = || format!("invalid operands for '{}': {} and {}", op, left.type_name(), right.type_name());
+
+  let result = match (op, left, right) {
+    (BinaryOperator::Add | BinaryOperator::Subtract, Object::Matrix(a), Object::Matrix(b)) => {
+      if a.rows != b.rows || a.cols != b.cols {
+        return Err(format!("dimension mismatch for '{}': {} and {}", op, a.shape(), b.shape()));
+      }
+
+      let data = a.data.iter().zip(b.data.iter()).map(|(a, b)| elementwise(a, b)).collect::<Result<_, _>>()?;
+      Matrix::new(a.rows, a.cols, data)
+    }
+    (BinaryOperator::Multiply, Object::Matrix(a), Object::Matrix(b)) => a.matmul(b, context)?,
+    (BinaryOperator::Multiply, Object::Matrix(a), Object::List(b)) => {
+      let b = vector(b).ok_or_else(invalid)?;
+
+      if a.cols != b.rows {
+        return Err(format!("dimension mismatch for '*': {} matrix and vector of length {}", a.shape(), b.rows));
+      }
+
+      return Ok(Object::List(Rc::new(a.matmul(&b, context)?.data)));
+    }
+    (BinaryOperator::Multiply, Object::List(a), Object::Matrix(b)) => {
+      let a = vector(a).ok_or_else(invalid)?.transpose();
+
+      if a.cols != b.rows {
+        return Err(format!("dimension mismatch for '*': vector of length {} and {} matrix", a.cols, b.shape()));
+      }
+
+      return Ok(Object::List(Rc::new(a.matmul(b, context)?.data)));
+    }
+    (BinaryOperator::Power, Object::Matrix(a), Object::Integer(exp)) => a.pow(*exp, context)?,
+    (_, Object::Matrix(a), scalar) if scalar.is_real() && !matches!(op, BinaryOperator::Modulo | BinaryOperator::Power) => {
+      a.map(|value| elementwise(value, scalar))?
+    }
+    (BinaryOperator::Add | BinaryOperator::Subtract | BinaryOperator::Multiply, scalar, Object::Matrix(b)) if scalar.is_real() => {
+      b.map(|value| elementwise(scalar, value))?
+    }
+    _ => return Err(invalid()),
+  };
+
+  Ok(Object::Matrix(Rc::new(result)))
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::decimal::DecimalContext;
+  use crate::matrix::Matrix;
+  use crate::object::Object;
+
+  fn matrix(rows: &[&[i64]]) -> Matrix {
+    let data = rows.iter().flat_map(|row| row.iter().map(|n| Object::Integer(*n))).collect();
+    Matrix::new(rows.len(), rows[0].len(), data)
+  }
+
+  #[test]
+  fn matrix_algebra() {
+    let context = DecimalContext::default();
+    let a = matrix(&[&[2, 1, 1], &[1, 3, 2], &[1, 0, 0]]);
+
+    assert_eq!(a.det(&context).unwrap(), Object::Integer(-1));
+    assert_eq!(a.matmul(&a.inverse(&context).unwrap(), &context).unwrap(), Matrix::identity(3));
+    assert_eq!(a.transpose().transpose(), a);
+    assert_eq!(a.trace(&context).unwrap(), Object::Integer(5));
+    assert_eq!(a.pow(3, &context).unwrap(), a.matmul(&a, &context).unwrap().matmul(&a, &context).unwrap());
+
+    let singular = matrix(&[&[1, 2], &[2, 4]]);
+    assert_eq!(singular.det(&context).unwrap(), Object::Integer(0));
+    assert_eq!(singular.rank(&context).unwrap(), 1);
+    assert!(singular.inverse(&context).is_err());
+    assert_eq!(matrix(&[&[0, 0], &[0, 0]]).rank(&context).unwrap(), 0);
+
+    let eigenvalues = matrix(&[&[2, 1], &[1, 2]]).symmetric_eigenvalues().unwrap();
+    assert!((eigenvalues[0] - 1.0).abs() < 1e-12 && (eigenvalues[1] - 3.0).abs() < 1e-12);
+    assert!(matrix(&[&[1, 2], &[3, 4]]).symmetric_eigenvalues().is_err());
+
+    let large = Matrix::identity(300);
+    assert!(large.pow(1000, &context).unwrap_err().contains("more than 20000000"));
+    assert!(large.matmul(&large, &context).is_err());
+    assert!(large.det(&context).is_err());
+    assert_eq!(Matrix::identity(50).pow(1000, &context).unwrap(), Matrix::identity(50));
+  }
+}
@@ -0,0 +1,784 @@
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::fmt;
+use std::rc::Rc;
+use crate::bigint::BigInt;
+use crate::complex::Complex;
+use crate::decimal::{self, Decimal, DecimalContext, RoundingMode};
+use crate::ast::BinaryOperator;
+use crate::matrix::Matrix;
+use crate::number;
+use crate::object::Object;
+use crate::range::Range;
+use crate::rational::Rational;
+
+/// What a native function may ask of the VM that calls it.
+pub trait Context {
+  /// Calls a function value, such as the one passed to `map`.
+  fn call(&mut self, callee: &Object, args: &[Object]) -> Result<Object, String>;
+
+  /// Applies a binary operator with the same rules as in expressions.
+  fn binary_op(&mut self, op: BinaryOperator, left: Object, right: Object) -> Result<Object, String>;
+
+  /// The context decimal results are rounded with.
+  fn decimal_context(&self) -> DecimalContext;
+}
+
+pub type NativeFn = Rc<dyn Fn(&mut dyn Context, &[Object]) -> Result<Object, String>>;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Arity {
+  Fixed(usize),
+  /// Accepts any number of arguments, with at least the given minimum.
+  Variadic(usize),
+  /// Accepts between the given minimum and maximum number of arguments.
+  Range(usize, usize),
+}
+
+impl Arity {
+  pub fn accepts(&self, count: usize) -> bool {
+    match self {
+      Arity::Fixed(n) => count == *n,
+      Arity::Variadic(min) => count >= *min,
+      Arity::Range(min, max) => (*min..=*max).contains(&count),
+    }
+  }
+}
+
+impl fmt::Display for Arity {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let (prefix, n) = match self {
+      Arity::Fixed(n) => (String::new(), *n),
+      Arity::Variadic(min) => ("at least ".to_string(), *min),
+      Arity::Range(min, max) => (format!("{} to ", min), *max),
+    };
+
+    write!(f, "{}{} argument{}", prefix, n, if n == 1 { "" } else { "s" })
+  }
+}
+
+#[derive(Clone)]
+pub struct Native {
+  pub name: String,
+  pub arity: Arity,
+  /// Whether the function always returns the same result for the same
+  /// arguments and has no side effects, so embedders may cache its results.
+  pub pure: bool,
+  pub function: NativeFn,
+}
+
+impl Native {
+  pub fn new<F>(name: &str, arity: Arity, pure: bool, function: F) -> Self
+  where
+    F: Fn(&[Object]) -> Result<Object, String> + 'static,
+  {
+    Native::with_context(name, arity, pure, move |_, args| function(args))
+  }
+
+  /// Like [`Native::new`], for functions that call back into the VM.
+  pub fn with_context<F>(name: &str, arity: Arity, pure: bool, function: F) -> Self
+  where
+    F: Fn(&mut dyn Context, &[Object]) -> Result<Object, String> + 'static,
+  {
+    Self {
+      name: name.to_string(),
+      arity,
+      pure,
+      function: Rc::new(function),
+    }
+  }
+}
+
+impl fmt::Debug for Native {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("Native")
+      .field("name", &self.name)
+      .field("arity", &self.arity)
+      .field("pure", &self.pure)
+      .finish()
+  }
+}
+
+fn number(args: &[Object], index: usize) -> Result<f64, String> {
+  match args[index].to_f64() {
+    Some(n) => Ok(n),
+    None => Err(format!("expected number for argument {}, got {}", index + 1, args[index].type_name())),
+  }
+}
+
+fn numeric(args: &[Object], index: usize) -> Result<Object, String> {
+  number(args, index)?;
+  Ok(args[index].clone())
+}
+
+fn complex(args: &[Object], index: usize) -> Result<Complex, String> {
+  match number::complex(&args[index]) {
+    Some(c) => Ok(c),
+    None => Err(format!("expected number for argument {}, got {}", index + 1, args[index].type_name())),
+  }
+}
+
+fn list(args: &[Object], index: usize) -> Result<&[Object], String> {
+  match &args[index] {
+    Object::List(items) => Ok(items),
+    value => Err(format!("expected list for argument {}, got {}", index + 1, value.type_name())),
+  }
+}
+
+/// The elements of a list or range argument; a range is expanded.
+fn elements<'a>(context: &dyn Context, args: &'a [Object], index: usize) -> Result<Cow<'a, [Object]>, String> {
+  match &args[index] {
+    Object::Range(r) if r.count().is_none_or(|len| len > MAX_LIST_LENGTH) => Err(format!("range has more than {} elements", MAX_LIST_LENGTH)),
+    Object::Range(r) => Ok(Cow::Owned(r.to_list(&context.decimal_context())?)),
+    _ => list(args, index).map(Cow::Borrowed),
+  }
+}
+
+fn matrix(args: &[Object], index: usize) -> Result<&Matrix, String> {
+  match &args[index] {
+    Object::Matrix(m) => Ok(m),
+    value => Err(format!("expected matrix for argument {}, got {}", index + 1, value.type_name())),
+  }
+}
+
+fn string(args: &[Object], index: usize) -> Result<&str, String> {
+  match &args[index] {
+    Object::String(s) => Ok(s),
+    value => Err(format!("expected string for argument {}, got {}", index + 1, value.type_name())),
+  }
+}
+
+/// A non-negative integer argument that fits in `usize`, such as an index.
+fn count(args: &[Object], index: usize) -> Result<usize, String> {
+  match integer(args, index)?.to_i64().map(usize::try_from) {
+    Some(Ok(n)) => Ok(n),
+    _ => Err(format!("expected a non-negative integer for argument {}, got {}", index + 1, args[index])),
+  }
+}
+
+fn integer(args: &[Object], index: usize) -> Result<BigInt, String> {
+  match number::big(&args[index]) {
+    Some(n) => Ok(n),
+    None => Err(format!("expected integer for argument {}, got {}", index + 1, args[index])),
+  }
+}
+
+macro_rules! unary {
+  ($name:expr, $f:expr) => {
+    Native::new($name, Arity::Fixed(1), true, |args| Ok(Object::Number($f(number(args, 0)?))))
+  };
+}
+
+/// Rounding functions keep exact inputs exact.
+macro_rules! rounding {
+  ($name:expr, $mode:expr) => {
+    Native::new($name, Arity::Fixed(1), true, |args| round_to(numeric(args, 0)?, 0, $mode))
+  };
+}
+
+macro_rules! binary {
+  ($name:expr, $f:expr) => {
+    Native::new($name, Arity::Fixed(2), true, |args| Ok(Object::Number($f(number(args, 0)?, number(args, 1)?))))
+  };
+}
+
+fn abs(args: &[Object]) -> Result<Object, String> {
+  if let Object::Complex(c) = &args[0] {
+    return Ok(Object::Number(c.abs()));
+  }
+
+  match numeric(args, 0)? {
+    Object::Number(n) => Ok(Object::Number(n.abs())),
+    value if number::compare(&value, &Object::Integer(0)) == Some(Ordering::Less) => Ok(number::negate(&value).unwrap()),
+    value => Ok(value),
+  }
+}
+
+/// Applies `real` to real arguments in its domain and `complex` otherwise,
+/// so `sqrt(-1)` is `i` rather than NaN.
+macro_rules! analytic {
+  ($name:expr, $domain:expr, $real:expr, $complex:expr) => {
+    Native::new($name, Arity::Fixed(1), true, |args| match args[0].to_f64() {
+      Some(n) if $domain(n) => Ok(Object::Number($real(n))),
+      _ => Ok(number::from_complex($complex(&complex(args, 0)?))),
+    })
+  };
+}
+
+fn re(args: &[Object]) -> Result<Object, String> {
+  match &args[0] {
+    Object::Complex(c) => Ok(Object::Number(c.re)),
+    _ => numeric(args, 0),
+  }
+}
+
+fn im(args: &[Object]) -> Result<Object, String> {
+  Ok(Object::Number(complex(args, 0)?.im))
+}
+
+fn conj(args: &[Object]) -> Result<Object, String> {
+  match &args[0] {
+    Object::Complex(c) => Ok(Object::Complex(c.conj())),
+    _ => numeric(args, 0),
+  }
+}
+
+fn arg(args: &[Object]) -> Result<Object, String> {
+  Ok(Object::Number(complex(args, 0)?.arg()))
+}
+
+/// `polar(r, theta)` builds the complex number with magnitude `r` and angle `theta`.
+fn polar(args: &[Object]) -> Result<Object, String> {
+  Ok(number::from_complex(Complex::from_polar(number(args, 0)?, number(args, 1)?)))
+}
+
+fn sign(args: &[Object]) -> Result<Object, String> {
+  match numeric(args, 0)? {
+    Object::Integer(n) => Ok(Object::Integer(n.signum())),
+    Object::BigInt(n) => Ok(Object::Integer(n.signum())),
+    Object::Rational(r) => Ok(Object::Integer(r.numer().signum())),
+    value => {
+      let n = number(&[value], 0)?;
+      Ok(Object::Number(if n == 0.0 { 0.0 } else { n.signum() }))
+    }
+  }
+}
+
+/// Rounds to `places` digits after the point, or to a power of ten when
+/// negative. Decimals stay decimals, exact values stay exact.
+fn round_to(value: Object, places: i64, mode: RoundingMode) -> Result<Object, String> {
+  let limit = decimal::MAX_SCALE as i64;
+
+  if !(-limit..=limit).contains(&places) {
+    return Err(format!("places must be between {} and {}, got {}", -limit, limit, places));
+  }
+
+  let (num, den) = match &value {
+    Object::Integer(_) | Object::BigInt(_) if places >= 0 => return Ok(value),
+    Object::Integer(_) | Object::BigInt(_) => (number::big(&value).unwrap(), BigInt::from_i64(1)),
+    Object::Rational(r) => (BigInt::from_i64(r.numer()), BigInt::from_i64(r.denom())),
+    Object::Decimal(d) => d.fraction(),
+    _ => {
+      let factor = 10f64.powi(places as i32);
+      return Ok(Object::Number(mode.round_f64(number(&[value], 0)? * factor) / factor));
+    }
+  };
+
+  let factor = decimal::pow10(places.unsigned_abs() as u32);
+  let places = places.max(0) as u32;
+
+  let rounded = match places {
+    0 => decimal::round_quotient(&num, &den.mul(&factor), mode).unwrap().mul(&factor),
+    _ => decimal::round_quotient(&num.mul(&factor), &den, mode).unwrap(),
+  };
+
+  if let Object::Decimal(_) = value {
+    return match rounded.to_i128() {
+      Some(units) => Ok(Object::Decimal(Decimal::new(units, places))),
+      None => Err("decimal overflow".to_string()),
+    };
+  }
+
+  let divisor = Object::BigInt(Rc::new(decimal::pow10(places)));
+  number::arithmetic(&BinaryOperator::Divide, &number::from_bigint(rounded), &divisor, &DecimalContext::default())
+}
+
+fn rounding_mode(args: &[Object], index: usize) -> Result<RoundingMode, String> {
+  let mode = match &args[index] {
+    Object::String(name) => RoundingMode::from_name(name),
+    _ => None,
+  };
+
+  match mode {
+    Some(mode) => Ok(mode),
+    None => {
+      let names: Vec<&str> = RoundingMode::ALL.iter().map(|(name, _)| *name).collect();
+      Err(format!("expected a rounding mode ({}) for argument {}, got {}", names.join(", "), index + 1, args[index]))
+    }
+  }
+}
+
+/// `round(x)`, `round(x, places)` and `round(x, places, mode)`, rounding
+/// half away from zero unless a mode is given.
+fn round(args: &[Object]) -> Result<Object, String> {
+  let places = match args.get(1) {
+    Some(_) => integer(args, 1)?.to_i64().unwrap_or(i64::MAX),
+    None => 0,
+  };
+
+  let mode = match args.get(2) {
+    Some(_) => rounding_mode(args, 2)?,
+    None => RoundingMode::HalfUp,
+  };
+
+  round_to(numeric(args, 0)?, places, mode)
+}
+
+/// Picks the argument that compares as `ordering` against all others,
+/// returning it unchanged so exact values stay exact.
+fn extreme(args: &[Object], ordering: Ordering) -> Result<Object, String> {
+  let mut best = numeric(args, 0)?;
+
+  for index in 1..args.len() {
+    let value = numeric(args, index)?;
+
+    if number::compare(&value, &best) == Some(ordering) {
+      best = value;
+    }
+  }
+
+  Ok(best)
+}
+
+fn min(args: &[Object]) -> Result<Object, String> {
+  extreme(args, Ordering::Less)
+}
+
+fn max(args: &[Object]) -> Result<Object, String> {
+  extreme(args, Ordering::Greater)
+}
+
+fn clamp(args: &[Object]) -> Result<Object, String> {
+  let (value, low, high) = (numeric(args, 0)?, numeric(args, 1)?, numeric(args, 2)?);
+
+  if number::compare(&low, &high) == Some(Ordering::Greater) {
+    return Err(format!("clamp() lower bound {} is greater than upper bound {}", low, high));
+  }
+
+  if number::compare(&value, &low) == Some(Ordering::Less) {
+    Ok(low)
+  } else if number::compare(&value, &high) == Some(Ordering::Greater) {
+    Ok(high)
+  } else {
+    Ok(value)
+  }
+}
+
+/// Largest argument accepted by `factorial`, which keeps results to a few
+/// tens of thousands of digits.
+const MAX_FACTORIAL: i64 = 10_000;
+
+fn factorial(args: &[Object]) -> Result<Object, String> {
+  let n = integer(args, 0)?;
+
+  match n.to_i64() {
+    Some(n) if n < 0 => Err(format!("factorial of negative number {}", n)),
+    Some(n) if n <= MAX_FACTORIAL => {
+      let result = (2..=n).fold(BigInt::from_i64(1), |product, k| product.mul(&BigInt::from_i64(k)));
+      Ok(number::from_bigint(result))
+    }
+    _ => Err(format!("argument {} is too large, the limit is {}", n, MAX_FACTORIAL)),
+  }
+}
+
+fn gcd(args: &[Object]) -> Result<Object, String> {
+  Ok(number::from_bigint(integer(args, 0)?.gcd(&integer(args, 1)?)))
+}
+
+/// Binomial coefficient, the number of ways to choose `k` items from `n`.
+fn comb(args: &[Object]) -> Result<Object, String> {
+  let (n, k) = (integer(args, 0)?, integer(args, 1)?);
+
+  if n.is_negative() || k.is_negative() {
+    return Err("arguments must not be negative".to_string());
+  }
+
+  if k > n {
+    return Ok(Object::Integer(0));
+  }
+
+  // C(n, k) = C(n, n - k); iterate over the smaller of the two.
+  let rest = n.sub(&k);
+  let k = k.min(rest);
+  let k = match k.to_i64() {
+    Some(k) if k <= MAX_FACTORIAL => k,
+    _ => return Err(format!("argument {} is too large, the limit is {}", k, MAX_FACTORIAL)),
+  };
+
+  let mut result = BigInt::from_i64(1);
+
+  for i in 0..k {
+    // Each partial product is itself a binomial coefficient, so the
+    // division is always exact.
+    result = result.mul(&n.sub(&BigInt::from_i64(i)));
+    result = result.divmod(&BigInt::from_i64(i + 1)).unwrap().0;
+  }
+
+  Ok(number::from_bigint(result))
+}
+
+/// Length of a list, or of a string in characters.
+fn len(args: &[Object]) -> Result<Object, String> {
+  match &args[0] {
+    Object::List(items) => Ok(Object::Integer(items.len() as i64)),
+    Object::Range(r) => Ok(number::from_bigint(r.len().clone())),
+    Object::Matrix(m) => Ok(Object::Integer(m.rows() as i64)),
+    _ => Ok(Object::Integer(string(args, 0)?.chars().count() as i64)),
+  }
+}
+
+/// `substr(s, start)` or `substr(s, start, length)`, counting characters
+/// from zero. A length past the end stops at the end.
+fn substr(args: &[Object]) -> Result<Object, String> {
+  let s = string(args, 0)?;
+  let start = count(args, 1)?;
+  let length = match args.get(2) {
+    Some(_) => count(args, 2)?,
+    None => usize::MAX,
+  };
+
+  if start > s.chars().count() {
+    return Err(format!("start {} is past the end of a string of length {}", start, s.chars().count()));
+  }
+
+  Ok(Object::String(s.chars().skip(start).take(length).collect::<String>().into()))
+}
+
+/// Replaces each `{}` or `{:.N}` in the first argument with the next
+/// argument, the latter with `N` decimal places. `{{` and `}}` are literal
+/// braces.
+fn format(args: &[Object]) -> Result<Object, String> {
+  let template = string(args, 0)?;
+  let mut values = args[1..].iter();
+  let mut result = String::new();
+  let mut chars = template.chars().peekable();
+
+  while let Some(ch) = chars.next() {
+    match ch {
+      '{' | '}' if chars.peek() == Some(&ch) => {
+        chars.next();
+        result.push(ch);
+      }
+      '{' => {
+        let mut spec = String::new();
+
+        loop {
+          match chars.next() {
+            Some('}') => break,
+            Some(ch) => spec.push(ch),
+            None => return Err("unterminated '{' in format string".to_string()),
+          }
+        }
+
+        let value = values.next().ok_or("not enough arguments for the format string")?;
+
+        match spec.strip_prefix(":.").map(str::parse::<usize>) {
+          None if spec.is_empty() => result.push_str(&value.to_string()),
+          Some(Ok(places)) => result.push_str(&format!("{:.*}", places, value)),
+          _ => return Err(format!("invalid placeholder '{{{}}}'", spec)),
+        }
+      }
+      '}' => return Err("unmatched '}' in format string".to_string()),
+      ch => result.push(ch),
+    }
+  }
+
+  if values.next().is_some() {
+    return Err("too many arguments for the format string".to_string());
+  }
+
+  Ok(Object::String(result.into()))
+}
+
+/// Parses a string as a number, exactly where the literal would be.
+/// Numbers are returned unchanged.
+fn num(args: &[Object]) -> Result<Object, String> {
+  let text = match &args[0] {
+    value if value.is_number() => return Ok(value.clone()),
+    _ => string(args, 0)?.trim(),
+  };
+
+  let (negative, digits) = match text.strip_prefix('-') {
+    Some(digits) => (true, digits),
+    None => (false, text.strip_prefix('+').unwrap_or(text)),
+  };
+
+  let value = if digits.is_empty() || !digits.starts_with(|ch: char| ch.is_ascii_digit() || ch == '.') {
+    None
+  } else if digits.bytes().all(|byte| byte.is_ascii_digit()) {
+    BigInt::parse(digits, 10).map(number::from_bigint)
+  } else {
+    match Rational::from_decimal_str(digits) {
+      Some(value) => Some(number::from_rational(value)),
+      None => digits.parse::<f64>().ok().filter(|value| value.is_finite()).map(Object::Number),
+    }
+  };
+
+  match value {
+    Some(value) if negative => Ok(number::negate(&value).unwrap()),
+    Some(value) => Ok(value),
+    None => Err(format!("cannot convert '{}' to a number", text)),
+  }
+}
+
+/// Largest list `range` will build, or a range argument will expand to.
+pub(crate) const MAX_LIST_LENGTH: usize = 1_000_000;
+
+/// Combines the elements with `op`, starting from `empty` when there are
+/// none.
+fn fold(context: &mut dyn Context, args: &[Object], op: BinaryOperator, empty: i64) -> Result<Object, String> {
+  match &*elements(context, args, 0)? {
+    [] => Ok(Object::Integer(empty)),
+    [first, rest @ ..] => rest.iter().try_fold(first.clone(), |total, item| {
+      context.binary_op(op.clone(), total, item.clone())
+    }),
+  }
+}
+
+/// Adds the elements with the usual `+`, so lists of money or quantities
+/// sum too. The sum of an empty list is 0, and a range of any length sums
+/// without being expanded.
+fn sum(context: &mut dyn Context, args: &[Object]) -> Result<Object, String> {
+  match &args[0] {
+    Object::Range(r) => r.sum(&context.decimal_context()),
+    _ => fold(context, args, BinaryOperator::Add, 0),
+  }
+}
+
+/// Multiplies the elements; the product of an empty list is 1.
+fn product(context: &mut dyn Context, args: &[Object]) -> Result<Object, String> {
+  fold(context, args, BinaryOperator::Multiply, 1)
+}
+
+fn mean(context: &mut dyn Context, args: &[Object]) -> Result<Object, String> {
+  let len = match &args[0] {
+    Object::Range(r) => number::from_bigint(r.len().clone()),
+    _ => Object::Integer(list(args, 0)?.len() as i64),
+  };
+
+  match len {
+    Object::Integer(0) => Err("mean of an empty list".to_string()),
+    len => {
+      let total = sum(context, args)?;
+      context.binary_op(BinaryOperator::Divide, total, len)
+    }
+  }
+}
+
+/// Sorts numbers, strings, or any values `<` can order, such as amounts of
+/// money in one currency. NaN sorts after every other number.
+fn sort(context: &mut dyn Context, args: &[Object]) -> Result<Object, String> {
+  let mut items = elements(context, args, 0)?.into_owned();
+
+  if items.iter().all(Object::is_real) {
+    items.sort_by(|left, right| {
+      number::compare(left, right).unwrap_or_else(|| left.to_f64().unwrap_or_default().total_cmp(&right.to_f64().unwrap_or_default()))
+    });
+  } else if items.iter().all(|item| matches!(item, Object::String(_))) {
+    items.sort_by_key(Object::to_string);
+  } else {
+    // Check every element against the first, so the comparisons made while
+    // sorting cannot fail part way through.
+    if let Some(first) = items.first() {
+      for item in &items[1..] {
+        context.binary_op(BinaryOperator::LessThan, item.clone(), first.clone())?;
+      }
+    }
+
+    items.sort_by(|left, right| {
+      let mut less = |left: &Object, right: &Object| {
+        context.binary_op(BinaryOperator::LessThan, left.clone(), right.clone()).is_ok_and(|less| Object::is_truthy(&less))
+      };
+
+      if less(left, right) {
+        Ordering::Less
+      } else if less(right, left) {
+        Ordering::Greater
+      } else {
+        Ordering::Equal
+      }
+    });
+  }
+
+  Ok(Object::List(Rc::new(items)))
+}
+
+/// The function and elements of `map(f, list)`, which may also be written
+/// `map(list, f)`.
+fn callback<'a>(context: &dyn Context, args: &'a [Object]) -> Result<(&'a Object, Cow<'a, [Object]>), String> {
+  match &args[0] {
+    Object::List(_) | Object::Range(_) => Ok((&args[1], elements(context, args, 0)?)),
+    _ => Ok((&args[0], elements(context, args, 1)?)),
+  }
+}
+
+/// `map(f, list)` applies `f` to each element.
+fn map(context: &mut dyn Context, args: &[Object]) -> Result<Object, String> {
+  let (function, items) = callback(context, args)?;
+  let items = items.iter()
+    .map(|item| context.call(function, std::slice::from_ref(item)))
+    .collect::<Result<Vec<_>, _>>()?;
+
+  Ok(Object::List(Rc::new(items)))
+}
+
+/// `filter(f, list)` keeps the elements for which `f` is truthy.
+fn filter(context: &mut dyn Context, args: &[Object]) -> Result<Object, String> {
+  let (function, all) = callback(context, args)?;
+  let mut items = Vec::new();
+
+  for item in all.iter() {
+    if Object::is_truthy(&context.call(function, std::slice::from_ref(item))?) {
+      items.push(item.clone());
+    }
+  }
+
+  Ok(Object::List(Rc::new(items)))
+}
+
+/// `reduce(f, list)` or `reduce(f, list, initial)` folds the elements from
+/// the left with `f(accumulator, element)`.
+fn reduce(context: &mut dyn Context, args: &[Object]) -> Result<Object, String> {
+  let (function, items) = callback(context, args)?;
+
+  let (initial, rest) = match (args.get(2), &*items) {
+    (Some(initial), items) => (initial.clone(), items),
+    (None, [first, rest @ ..]) => (first.clone(), rest),
+    (None, []) => return Err("reduce of an empty list with no initial value".to_string()),
+  };
+
+  rest.iter().try_fold(initial, |accumulator, item| context.call(function, &[accumulator, item.clone()]))
+}
+
+/// `range(stop)`, `range(start, stop)` or `range(start, stop, step)`, with
+/// `stop` excluded: the same lazy range as `start..stop step step`.
+fn range(context: &mut dyn Context, args: &[Object]) -> Result<Object, String> {
+  for index in 0..args.len() {
+    numeric(args, index)?;
+  }
+
+  let (start, stop, step) = match args {
+    [stop] => (Object::Integer(0), stop.clone(), Object::Integer(1)),
+    [start, stop] => (start.clone(), stop.clone(), Object::Integer(1)),
+    _ => (args[0].clone(), args[1].clone(), args[2].clone()),
+  };
+
+  Ok(Object::Range(Rc::new(Range::new(start, stop, step, false, &context.decimal_context())?)))
+}
+
+/// Largest matrix `identity` will build, in rows.
+const MAX_MATRIX_SIZE: usize = 1000;
+
+fn det(context: &mut dyn Context, args: &[Object]) -> Result<Object, String> {
+  matrix(args, 0)?.det(&context.decimal_context())
+}
+
+fn inv(context: &mut dyn Context, args: &[Object]) -> Result<Object, String> {
+  Ok(Object::Matrix(Rc::new(matrix(args, 0)?.inverse(&context.decimal_context())?)))
+}
+
+/// Transposes a matrix; a list is treated as a row and becomes a column.
+fn transpose(args: &[Object]) -> Result<Object, String> {
+  let transposed = match &args[0] {
+    Object::List(_) => Matrix::from_rows(args).map(|row| row.transpose()),
+    _ => Some(matrix(args, 0)?.transpose()),
+  };
+
+  match transposed {
+    Some(transposed) => Ok(Object::Matrix(Rc::new(transposed))),
+    None => Err("expected a matrix or a non-empty list of real numbers".to_string()),
+  }
+}
+
+fn identity(args: &[Object]) -> Result<Object, String> {
+  match count(args, 0)? {
+    n if n == 0 || n > MAX_MATRIX_SIZE => Err(format!("size must be between 1 and {}, got {}", MAX_MATRIX_SIZE, n)),
+    n => Ok(Object::Matrix(Rc::new(Matrix::identity(n)))),
+  }
+}
+
+/// `solve(A, b)` finds `x` with `A * x == b`, for a list `b` or a matrix
+/// of right-hand sides.
+fn solve(context: &mut dyn Context, args: &[Object]) -> Result<Object, String> {
+  let a = matrix(args, 0)?;
+
+  let rhs = match &args[1] {
+    Object::List(items) if items.iter().all(Object::is_real) => Matrix::new(items.len(), 1, items.to_vec()),
+    Object::Matrix(m) => Matrix::clone(m),
+    value => return Err(format!("expected list or matrix for argument 2, got {}", value.type_name())),
+  };
+
+  let solution = a.solve(&rhs, &context.decimal_context())?;
+
+  match args[1] {
+    Object::List(_) => Ok(Object::List(Rc::new((0..solution.rows()).map(|row| solution.get(row, 0).clone()).collect()))),
+    _ => Ok(Object::Matrix(Rc::new(solution))),
+  }
+}
+
+fn rank(context: &mut dyn Context, args: &[Object]) -> Result<Object, String> {
+  Ok(Object::Integer(matrix(args, 0)?.rank(&context.decimal_context())? as i64))
+}
+
+fn trace(context: &mut dyn Context, args: &[Object]) -> Result<Object, String> {
+  matrix(args, 0)?.trace(&context.decimal_context())
+}
+
+/// Eigenvalues of a symmetric matrix, in ascending order.
+fn eig(args: &[Object]) -> Result<Object, String> {
+  let values = matrix(args, 0)?.symmetric_eigenvalues()?;
+  Ok(Object::List(Rc::new(values.into_iter().map(Object::Number).collect())))
+}
+
+/// The built-in functions every VM starts with.
+pub fn builtins() -> Vec<Native> {
+  vec![
+    unary!("sin", f64::sin),
+    unary!("cos", f64::cos),
+    unary!("tan", f64::tan),
+    unary!("asin", f64::asin),
+    unary!("acos", f64::acos),
+    unary!("atan", f64::atan),
+    binary!("atan2", f64::atan2),
+    analytic!("sqrt", |n: f64| n >= 0.0, f64::sqrt, Complex::sqrt),
+    unary!("cbrt", f64::cbrt),
+    analytic!("exp", |_| true, f64::exp, Complex::exp),
+    analytic!("ln", |n: f64| n >= 0.0, f64::ln, Complex::ln),
+    unary!("log10", f64::log10),
+    unary!("log2", f64::log2),
+    binary!("log", f64::log),
+    Native::new("abs", Arity::Fixed(1), true, abs),
+    rounding!("floor", RoundingMode::Floor),
+    rounding!("ceil", RoundingMode::Ceiling),
+    Native::new("round", Arity::Range(1, 3), true, round),
+    rounding!("trunc", RoundingMode::Down),
+    Native::new("sign", Arity::Fixed(1), true, sign),
+    Native::new("min", Arity::Variadic(1), true, min),
+    Native::new("max", Arity::Variadic(1), true, max),
+    binary!("hypot", f64::hypot),
+    Native::new("clamp", Arity::Fixed(3), true, clamp),
+    Native::new("re", Arity::Fixed(1), true, re),
+    Native::new("im", Arity::Fixed(1), true, im),
+    Native::new("conj", Arity::Fixed(1), true, conj),
+    Native::new("arg", Arity::Fixed(1), true, arg),
+    Native::new("polar", Arity::Fixed(2), true, polar),
+    Native::new("factorial", Arity::Fixed(1), true, factorial),
+    Native::new("gcd", Arity::Fixed(2), true, gcd),
+    Native::new("comb", Arity::Fixed(2), true, comb),
+    unary!("float", |n| n),
+    Native::new("rates_timestamp", Arity::Fixed(0), false, |_| Err("no exchange rates loaded".to_string())),
+    Native::new("len", Arity::Fixed(1), true, len),
+    Native::new("upper", Arity::Fixed(1), true, |args| Ok(Object::String(Rc::from(string(args, 0)?.to_uppercase())))),
+    Native::new("lower", Arity::Fixed(1), true, |args| Ok(Object::String(Rc::from(string(args, 0)?.to_lowercase())))),
+    Native::new("substr", Arity::Range(2, 3), true, substr),
+    Native::new("format", Arity::Variadic(1), true, format),
+    Native::new("str", Arity::Fixed(1), true, |args| Ok(Object::String(Rc::from(args[0].to_string())))),
+    Native::new("num", Arity::Fixed(1), true, num),
+    Native::with_context("sum", Arity::Fixed(1), true, sum),
+    Native::with_context("product", Arity::Fixed(1), true, product),
+    Native::with_context("mean", Arity::Fixed(1), true, mean),
+    Native::with_context("sort", Arity::Fixed(1), true, sort),
+    Native::with_context("map", Arity::Fixed(2), false, map),
+    Native::with_context("filter", Arity::Fixed(2), false, filter),
+    Native::with_context("reduce", Arity::Range(2, 3), false, reduce),
+    Native::with_context("range", Arity::Range(1, 3), true, range),
+    Native::with_context("det", Arity::Fixed(1), true, det),
+    Native::with_context("inv", Arity::Fixed(1), true, inv),
+    Native::new("transpose", Arity::Fixed(1), true, transpose),
+    Native::new("identity", Arity::Fixed(1), true, identity),
+    Native::with_context("solve", Arity::Fixed(2), true, solve),
+    Native::with_context("rank", Arity::Fixed(1), true, rank),
+    Native::with_context("trace", Arity::Fixed(1), true, trace),
+    Native::new("eig", Arity::Fixed(1), true, eig),
+  ]
+}
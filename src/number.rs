@@ -0,0 +1,351 @@
+use std::cmp::Ordering;
+use std::rc::Rc;
+use crate::ast::BinaryOperator;
+use crate::bigint::BigInt;
+use crate::complex::Complex;
+use crate::currency::Money;
+use crate::decimal::{Decimal, DecimalContext};
+use crate::object::Object;
+use crate::rational::Rational;
+
+/// Returns the value as an exact fraction if it is an integer or rational.
+pub fn exact(value: &Object) -> Option<Rational> {
+  match value {
+    Object::Integer(n) => Some(Rational::from_integer(*n)),
+    Object::Rational(r) => Some(*r),
+    _ => None,
+  }
+}
+
+/// Wraps a fraction, collapsing whole numbers to `Object::Integer`.
+pub fn from_rational(value: Rational) -> Object {
+  if value.is_integer() {
+    Object::Integer(value.numer())
+  } else {
+    Object::Rational(value)
+  }
+}
+
+/// Returns the value as a big integer if it is an integer of either size.
+pub fn big(value: &Object) -> Option<BigInt> {
+  match value {
+    Object::Integer(n) => Some(BigInt::from_i64(*n)),
+    Object::BigInt(n) => Some(BigInt::clone(n)),
+    _ => None,
+  }
+}
+
+/// Wraps a big integer, collapsing values that fit to `Object::Integer`.
+pub fn from_bigint(value: BigInt) -> Object {
+  match value.to_i64() {
+    Some(n) => Object::Integer(n),
+    None => Object::BigInt(Rc::new(value)),
+  }
+}
+
+/// Returns the value as a complex number if it is numeric.
+pub fn complex(value: &Object) -> Option<Complex> {
+  match value {
+    Object::Complex(c) => Some(*c),
+    _ => Some(Complex::new(value.to_f64()?, 0.0)),
+  }
+}
+
+/// Wraps a complex number, collapsing values with no imaginary part to floats.
+pub fn from_complex(value: Complex) -> Object {
+  if value.im == 0.0 {
+    Object::Number(value.re)
+  } else {
+    Object::Complex(value)
+  }
+}
+
+/// Returns the exact value as a numerator and positive denominator.
+pub(crate) fn fraction(value: &Object) -> Option<(BigInt, BigInt)> {
+  match value {
+    Object::Integer(_) | Object::BigInt(_) => Some((big(value)?, BigInt::from_i64(1))),
+    Object::Rational(r) => Some((BigInt::from_i64(r.numer()), BigInt::from_i64(r.denom()))),
+    Object::Decimal(d) => Some(d.fraction()),
+    _ => None,
+  }
+}
+
+/// Exact results that would exceed this many bits are not computed, rather
+/// than spending unbounded time and memory: integer arithmetic is an error,
+/// and powers of fractions fall back to floating point.
+const MAX_INTEGER_BITS: u64 = 1 << 20;
+
+fn float_op(op: &BinaryOperator, left: f64, right: f64) -> Object {
+  let result = match op {
+    BinaryOperator::Add => left + right,
+    BinaryOperator::Subtract => left - right,
+    BinaryOperator::Multiply => left * right,
+    BinaryOperator::Divide => left / right,
+    BinaryOperator::Modulo => left % right,
+    BinaryOperator::Power => left.powf(right),
+    _ => unreachable!("{} is not arithmetic", op),
+  };
+
+  Object::Number(result)
+}
+
+fn rational_op(op: &BinaryOperator, left: Rational, right: Rational) -> Result<Object, String> {
+  let zero = Rational::from_integer(0);
+
+  if matches!(op, BinaryOperator::Divide | BinaryOperator::Modulo) && right == zero {
+    return Err("division by zero".to_string());
+  }
+
+  if *op == BinaryOperator::Power && left == zero && right.is_integer() && right < zero {
+    return Err("division by zero".to_string());
+  }
+
+  let result = match op {
+    BinaryOperator::Add => left.checked_add(right),
+    BinaryOperator::Subtract => left.checked_sub(right),
+    BinaryOperator::Multiply => left.checked_mul(right),
+    BinaryOperator::Divide => left.checked_div(right),
+    BinaryOperator::Modulo => left.checked_rem(right),
+    BinaryOperator::Power if right.is_integer() => left.checked_pow(right.numer()),
+    _ => None,
+  };
+
+  // Results that do not fit in a 64-bit fraction, and irrational powers,
+  // fall back to floating point.
+  Ok(match result {
+    Some(result) => from_rational(result),
+    None => float_op(op, left.to_f64(), right.to_f64()),
+  })
+}
+
+fn complex_op(op: &BinaryOperator, left: Complex, right: Complex) -> Result<Object, String> {
+  let result = match op {
+    BinaryOperator::Add => left + right,
+    BinaryOperator::Subtract => left - right,
+    BinaryOperator::Multiply => left * right,
+    BinaryOperator::Divide if right.re == 0.0 && right.im == 0.0 => return Err("division by zero".to_string()),
+    BinaryOperator::Divide => left / right,
+    BinaryOperator::Power => left.pow(right),
+    BinaryOperator::Modulo => return Err("'%' is not defined for complex numbers".to_string()),
+    _ => unreachable!("{} is not arithmetic", op),
+  };
+
+  Ok(from_complex(result))
+}
+
+/// Computes the exact result as a fraction, then rounds it to the context's
+/// scale. Mixing a decimal with a float gives a float.
+fn decimal_op(op: &BinaryOperator, left: &Object, right: &Object, context: &DecimalContext) -> Result<Object, String> {
+  let ((ln, ld), (rn, rd)) = match (fraction(left), fraction(right)) {
+    (Some(left), Some(right)) => (left, right),
+    _ => return Ok(float_op(op, left.to_f64().unwrap_or(f64::NAN), right.to_f64().unwrap_or(f64::NAN))),
+  };
+
+  if matches!(op, BinaryOperator::Divide | BinaryOperator::Modulo) && rn.is_zero() {
+    return Err("division by zero".to_string());
+  }
+
+  if ln.bits() + ld.bits() + rn.bits() + rd.bits() > MAX_INTEGER_BITS {
+    return Err("integer too large".to_string());
+  }
+
+  let (num, den) = match op {
+    BinaryOperator::Add => (ln.mul(&rd).add(&rn.mul(&ld)), ld.mul(&rd)),
+    BinaryOperator::Subtract => (ln.mul(&rd).sub(&rn.mul(&ld)), ld.mul(&rd)),
+    BinaryOperator::Multiply => (ln.mul(&rn), ld.mul(&rd)),
+    BinaryOperator::Divide => (ln.mul(&rd), ld.mul(&rn)),
+    BinaryOperator::Modulo => (ln.mul(&rd).divmod(&rn.mul(&ld)).unwrap().1, ld.mul(&rd)),
+    BinaryOperator::Power => {
+      let exp = if rd.to_i64() == Some(1) { rn.to_i64() } else { None };
+
+      match exp.and_then(|exp| Some((exp, u32::try_from(exp.unsigned_abs()).ok()?))) {
+        Some((exp, _)) if exp < 0 && ln.is_zero() => return Err("division by zero".to_string()),
+        Some((exp, abs)) if (ln.bits() + ld.bits()).saturating_mul(abs as u64) <= MAX_INTEGER_BITS => {
+          if exp < 0 { (ld.pow(abs), ln.pow(abs)) } else { (ln.pow(abs), ld.pow(abs)) }
+        },
+        _ => return Ok(float_op(op, left.to_f64().unwrap_or(f64::NAN), right.to_f64().unwrap_or(f64::NAN))),
+      }
+    },
+    _ => unreachable!("{} is not arithmetic", op),
+  };
+
+  match Decimal::from_fraction(&num, &den, context.scale, context.rounding) {
+    Some(result) => Ok(Object::Decimal(result)),
+    None => Err("decimal overflow".to_string()),
+  }
+}
+
+/// Wraps an exact fraction of big integers. A result that is not whole has
+/// to fit in a `Rational`; otherwise it is an error rather than rounded.
+fn from_fraction(op: &BinaryOperator, num: BigInt, den: BigInt) -> Result<Object, String> {
+  let divisor = num.gcd(&den);
+  let (mut num, mut den) = (num.divmod(&divisor).unwrap().0, den.divmod(&divisor).unwrap().0);
+
+  if den.is_negative() {
+    num = num.neg();
+    den = den.neg();
+  }
+
+  if den.to_i64() == Some(1) {
+    return Ok(from_bigint(num));
+  }
+
+  match num.to_i64().zip(den.to_i64()).and_then(|(num, den)| Rational::new(num, den)) {
+    Some(fraction) => Ok(Object::Rational(fraction)),
+    None => Err(format!("exact result of '{}' is too large for a fraction", op)),
+  }
+}
+
+/// Arithmetic between a big integer and a fraction, computed exactly.
+/// Powers are only exact for integer operands, so they use floating point.
+fn fraction_op(op: &BinaryOperator, left: &Object, right: &Object) -> Result<Object, String> {
+  let ((ln, ld), (rn, rd)) = match (fraction(left), fraction(right)) {
+    (Some(left), Some(right)) => (left, right),
+    _ => return Ok(float_op(op, left.to_f64().unwrap_or(f64::NAN), right.to_f64().unwrap_or(f64::NAN))),
+  };
+
+  if matches!(op, BinaryOperator::Divide | BinaryOperator::Modulo) && rn.is_zero() {
+    return Err("division by zero".to_string());
+  }
+
+  if ln.bits() + ld.bits() + rn.bits() + rd.bits() > MAX_INTEGER_BITS {
+    return Err("integer too large".to_string());
+  }
+
+  let (num, den) = match op {
+    BinaryOperator::Add => (ln.mul(&rd).add(&rn.mul(&ld)), ld.mul(&rd)),
+    BinaryOperator::Subtract => (ln.mul(&rd).sub(&rn.mul(&ld)), ld.mul(&rd)),
+    BinaryOperator::Multiply => (ln.mul(&rn), ld.mul(&rd)),
+    BinaryOperator::Divide => (ln.mul(&rd), ld.mul(&rn)),
+    BinaryOperator::Modulo => (ln.mul(&rd).divmod(&rn.mul(&ld)).unwrap().1, ld.mul(&rd)),
+    BinaryOperator::Power => return Ok(float_op(op, left.to_f64().unwrap_or(f64::NAN), right.to_f64().unwrap_or(f64::NAN))),
+    _ => unreachable!("{} is not arithmetic", op),
+  };
+
+  from_fraction(op, num, den)
+}
+
+fn bigint_op(op: &BinaryOperator, left: BigInt, right: BigInt) -> Result<Object, String> {
+  if matches!(op, BinaryOperator::Divide | BinaryOperator::Modulo) && right.is_zero() {
+    return Err("division by zero".to_string());
+  }
+
+  let bits = match op {
+    BinaryOperator::Add | BinaryOperator::Subtract => left.bits().max(right.bits()) + 1,
+    BinaryOperator::Multiply => left.bits() + right.bits(),
+    _ => 0,
+  };
+
+  if bits > MAX_INTEGER_BITS {
+    return Err("integer too large".to_string());
+  }
+
+  let result = match op {
+    BinaryOperator::Add => left.add(&right),
+    BinaryOperator::Subtract => left.sub(&right),
+    BinaryOperator::Multiply => left.mul(&right),
+    BinaryOperator::Modulo => left.divmod(&right).unwrap().1,
+    BinaryOperator::Divide => {
+      let (quotient, remainder) = left.divmod(&right).unwrap();
+
+      if !remainder.is_zero() {
+        return from_fraction(op, left, right);
+      }
+
+      quotient
+    },
+    BinaryOperator::Power => {
+      let exp = right.to_i64().and_then(|exp| u32::try_from(exp).ok());
+
+      match exp {
+        Some(exp) if left.bits().saturating_mul(exp as u64) <= MAX_INTEGER_BITS => left.pow(exp),
+        _ if right.is_negative() => return Ok(float_op(op, left.to_f64(), right.to_f64())),
+        // 0, 1 and -1 stay small however large the exponent is.
+        _ if left.bits() <= 1 => {
+          let odd = !right.divmod(&BigInt::from_i64(2)).unwrap().1.is_zero();
+          if left.is_negative() && odd { left } else { left.abs() }
+        },
+        _ => return Err("result of '^' is too large".to_string()),
+      }
+    },
+    _ => unreachable!("{} is not arithmetic", op),
+  };
+
+  Ok(from_bigint(result))
+}
+
+fn integer_op(op: &BinaryOperator, left: i64, right: i64) -> Result<Object, String> {
+  let result = match op {
+    BinaryOperator::Add => left.checked_add(right),
+    BinaryOperator::Subtract => left.checked_sub(right),
+    BinaryOperator::Multiply => left.checked_mul(right),
+    BinaryOperator::Power if right >= 0 => u32::try_from(right).ok().and_then(|exp| left.checked_pow(exp)),
+    BinaryOperator::Modulo if right != 0 => left.checked_rem(right),
+    // `i64::MIN / -1` has no remainder but overflows, so it promotes below.
+    BinaryOperator::Divide if right != 0 && left.checked_rem(right).unwrap_or(0) == 0 => left.checked_div(right),
+    _ => return rational_op(op, Rational::from_integer(left), Rational::from_integer(right)),
+  };
+
+  // Overflow promotes to a big integer.
+  match result {
+    Some(result) => Ok(Object::Integer(result)),
+    None => bigint_op(op, BigInt::from_i64(left), BigInt::from_i64(right)),
+  }
+}
+
+/// Applies an arithmetic operator to two numbers, staying exact while both
+/// operands are exact and falling back to floating point otherwise. Results
+/// involving a decimal are rounded according to `context`, and exact results
+/// involving a big integer are never rounded.
+pub fn arithmetic(op: &BinaryOperator, left: &Object, right: &Object, context: &DecimalContext) -> Result<Object, String> {
+  match (left, right) {
+    (Object::Integer(left), Object::Integer(right)) => integer_op(op, *left, *right),
+    (Object::Complex(_), _) | (_, Object::Complex(_)) => match (complex(left), complex(right)) {
+      (Some(left), Some(right)) => complex_op(op, left, right),
+      _ => Err(format!("invalid operands for '{}'", op)),
+    },
+    (Object::Decimal(_), _) | (_, Object::Decimal(_)) => decimal_op(op, left, right, context),
+    _ => match (exact(left), exact(right)) {
+      (Some(left), Some(right)) => rational_op(op, left, right),
+      _ if matches!(left, Object::BigInt(_)) || matches!(right, Object::BigInt(_)) => match (big(left), big(right)) {
+        (Some(left), Some(right)) => bigint_op(op, left, right),
+        _ => fraction_op(op, left, right),
+      },
+      _ => Ok(float_op(op, left.to_f64().unwrap_or(f64::NAN), right.to_f64().unwrap_or(f64::NAN))),
+    },
+  }
+}
+
+pub fn compare(left: &Object, right: &Object) -> Option<Ordering> {
+  match (exact(left), exact(right)) {
+    (Some(left), Some(right)) => Some(left.cmp(&right)),
+    _ => match (fraction(left), fraction(right)) {
+      (Some((ln, ld)), Some((rn, rd))) => Some(ln.mul(&rd).cmp(&rn.mul(&ld))),
+      _ => left.to_f64()?.partial_cmp(&right.to_f64()?),
+    },
+  }
+}
+
+/// Numeric equality; complex numbers are equal when both parts are.
+pub fn equal(left: &Object, right: &Object) -> bool {
+  match (left, right) {
+    (Object::Complex(_), _) | (_, Object::Complex(_)) => complex(left) == complex(right),
+    _ => compare(left, right) == Some(Ordering::Equal),
+  }
+}
+
+pub fn negate(value: &Object) -> Option<Object> {
+  match value {
+    Object::Integer(n) => Some(n.checked_neg().map_or_else(|| from_bigint(BigInt::from_i64(*n).neg()), Object::Integer)),
+    Object::BigInt(n) => Some(from_bigint(n.neg())),
+    Object::Rational(r) => Some(r.checked_neg().map_or(Object::Number(-r.to_f64()), Object::Rational)),
+    Object::Complex(c) => Some(Object::Complex(-*c)),
+    Object::Quantity(q) => Some(Object::Quantity(Rc::new(q.with_value(-q.value)))),
+    Object::List(items) => Some(Object::List(Rc::new(items.iter().map(negate).collect::<Option<_>>()?))),
+    Object::Matrix(m) => Some(Object::Matrix(Rc::new(m.map(|value| negate(value).ok_or_else(String::new)).ok()?))),
+    Object::Money(m) => Some(Object::Money(Rc::new(Money::new(negate(&m.amount)?, m.currency)))),
+    Object::Decimal(d) => Some(d.checked_neg().map_or(Object::Number(-d.to_f64()), Object::Decimal)),
+    Object::Number(n) => Some(Object::Number(-n)),
+    _ => None,
+  }
+}
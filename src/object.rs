@@ -1,16 +1,157 @@
 use std::fmt;
+use std::rc::Rc;
+use crate::bigint::BigInt;
+use crate::bytecode::Bytecode;
+use crate::complex::Complex;
+use crate::currency::Money;
+use crate::decimal::Decimal;
+use crate::matrix::Matrix;
+use crate::range::Range;
+use crate::rational::Rational;
+use crate::units::Quantity;
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+/// How deeply lists may nest. Dropping, printing and comparing a list
+/// recurse into its elements, so deeper lists could overflow the stack.
+pub const MAX_LIST_DEPTH: usize = 256;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Object {
   Number(f64),
+  Integer(i64),
+  /// An integer too large for `i64`; smaller values are always `Integer`.
+  BigInt(Rc<BigInt>),
+  Rational(Rational),
+  /// A fixed-point base-10 number, produced in decimal mode.
+  Decimal(Decimal),
+  /// Always has a non-zero imaginary part; real results collapse to `Number`.
+  Complex(Complex),
+  /// A number with units; never dimensionless.
+  Quantity(Rc<Quantity>),
+  /// An amount tagged with its currency.
+  Money(Rc<Money>),
   Boolean(bool),
+  String(Rc<str>),
+  List(Rc<Vec<Object>>),
+  /// Written as a list of equally long lists of real numbers.
+  Matrix(Rc<Matrix>),
+  Range(Rc<Range>),
+  /// A function from the VM's native registry, by index.
+  Native(usize),
+  Function(Rc<Function>),
+  /// A function together with the values it captured from enclosing
+  /// functions when it was created.
+  Closure(Rc<Closure>),
+  /// The result of a program whose last statement leaves no value, such as
+  /// a loop.
+  Unit,
+}
+
+/// Where a function finds a variable of an enclosing function, read once
+/// when the closure is created.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Capture {
+  /// A slot of the function that creates the closure.
+  Local(usize),
+  /// One of the values captured by the function that creates the closure.
+  Upvalue(usize),
+}
+
+/// A function defined in the expression language, as in `f(x) = x^2`,
+/// compiled into its own chunk of bytecode.
+#[derive(Debug)]
+pub struct Function {
+  pub name: String,
+  pub params: Vec<String>,
+  pub(crate) chunk: Bytecode,
+  pub(crate) captures: Vec<Capture>,
+}
+
+#[derive(Debug)]
+pub struct Closure {
+  pub function: Rc<Function>,
+  pub upvalues: Vec<Object>,
+}
+
+/// Functions are equal only to themselves.
+impl PartialEq for Function {
+  fn eq(&self, other: &Self) -> bool {
+    std::ptr::eq(self, other)
+  }
+}
+
+/// Closures are equal only to themselves.
+impl PartialEq for Closure {
+  fn eq(&self, other: &Self) -> bool {
+    std::ptr::eq(self, other)
+  }
 }
 
 impl Object {
+  pub fn type_name(&self) -> &'static str {
+    match self {
+      Object::Number(_) | Object::Integer(_) | Object::BigInt(_) | Object::Rational(_) | Object::Decimal(_) => "number",
+      Object::Complex(_) => "complex",
+      Object::Quantity(_) => "quantity",
+      Object::Money(_) => "money",
+      Object::Boolean(_) => "boolean",
+      Object::String(_) => "string",
+      Object::List(_) => "list",
+      Object::Matrix(_) => "matrix",
+      Object::Range(_) => "range",
+      Object::Native(_) | Object::Function(_) | Object::Closure(_) => "function",
+      Object::Unit => "unit",
+    }
+  }
+
+  /// How many lists deep the value is: 0 for anything but a list, and 1 for
+  /// a list of other values.
+  pub fn depth(&self) -> usize {
+    match self {
+      Object::List(items) => 1 + items.iter().map(Object::depth).max().unwrap_or(0),
+      _ => 0,
+    }
+  }
+
+  pub fn is_number(&self) -> bool {
+    matches!(self, Object::Number(_) | Object::Integer(_) | Object::BigInt(_) | Object::Rational(_) | Object::Decimal(_) | Object::Complex(_))
+  }
+
+  /// Whether the value is a number on the real line, and so can be ordered.
+  pub fn is_real(&self) -> bool {
+    self.is_number() && !matches!(self, Object::Complex(_))
+  }
+
+  /// Converts any numeric value to a float, or `None` for non-numbers.
+  pub fn to_f64(&self) -> Option<f64> {
+    match self {
+      Object::Number(n) => Some(*n),
+      Object::Integer(n) => Some(*n as f64),
+      Object::BigInt(n) => Some(n.to_f64()),
+      Object::Rational(r) => Some(r.to_f64()),
+      Object::Decimal(d) => Some(d.to_f64()),
+      _ => None,
+    }
+  }
+
   pub fn is_truthy(object: &Object) -> bool {
     match object {
       Object::Boolean(b) => *b,
       Object::Number(n) => *n != 0.0,
+      Object::Integer(n) => *n != 0,
+      Object::BigInt(_) => true,
+      Object::Rational(_) => true,
+      Object::Decimal(d) => !d.is_zero(),
+      Object::Complex(_) => true,
+      Object::Quantity(q) => q.value != 0.0,
+      Object::Money(m) => Object::is_truthy(&m.amount),
+      Object::String(s) => !s.is_empty(),
+      Object::List(items) => !items.is_empty(),
+      Object::Matrix(_) => true,
+      Object::Range(r) => !r.is_empty(),
+      Object::Native(_) => true,
+      Object::Function(_) => true,
+      Object::Closure(_) => true,
+      Object::Unit => false,
     }
   }
 
@@ -19,11 +160,50 @@ impl Object {
   }
 }
 
+/// A precision (`{:.2}`) pads whole numbers with zeros, matching how
+/// fractions print.
 impl fmt::Display for Object {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     match self {
-      Object::Number(n) => write!(f, "{}", n),
+      Object::Number(n) => fmt::Display::fmt(n, f),
+      Object::Integer(n) => match f.precision() {
+        Some(places) if places > 0 => write!(f, "{}.{:0<places$}", n, ""),
+        _ => fmt::Display::fmt(n, f),
+      },
+      Object::BigInt(n) => match f.precision() {
+        Some(places) if places > 0 => write!(f, "{}.{:0<places$}", n, ""),
+        _ => fmt::Display::fmt(n, f),
+      },
+      Object::Rational(r) => fmt::Display::fmt(r, f),
+      Object::Decimal(d) => fmt::Display::fmt(d, f),
+      Object::Complex(c) => fmt::Display::fmt(c, f),
+      Object::Quantity(q) => fmt::Display::fmt(q, f),
+      Object::Money(m) => fmt::Display::fmt(m, f),
       Object::Boolean(b) => write!(f, "{}", b),
+      Object::String(s) => fmt::Display::fmt(s, f),
+      Object::List(items) => {
+        write!(f, "[")?;
+
+        for (index, item) in items.iter().enumerate() {
+          if index > 0 {
+            write!(f, ", ")?;
+          }
+
+          // Strings inside lists are quoted so `["a, b"]` reads unambiguously.
+          match item {
+            Object::String(s) => write!(f, "{:?}", s)?,
+            _ => fmt::Display::fmt(item, f)?,
+          }
+        }
+
+        write!(f, "]")
+      }
+      Object::Matrix(m) => fmt::Display::fmt(m, f),
+      Object::Range(r) => fmt::Display::fmt(r, f),
+      Object::Native(_) => write!(f, "<native fn>"),
+      Object::Function(function) => write!(f, "<fn {}>", function.name),
+      Object::Closure(closure) => write!(f, "<fn {}>", closure.function.name),
+      Object::Unit => write!(f, "()"),
     }
   }
-}
\ No newline at end of file
+}
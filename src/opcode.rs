@@ -2,17 +2,89 @@
 #[derive(Debug, Copy, Clone)]
 pub enum Opcode {
   Constant(usize),
+  GetGlobal(usize),
+  /// Pushes the argument in the given slot of the current call frame.
+  GetLocal(usize),
+  /// Stores the top of the stack in the given slot, leaving it there.
+  SetLocal(usize),
+  /// Pushes a value captured by the running closure.
+  GetUpvalue(usize),
+  /// Pushes a closure over the function constant, capturing what it uses
+  /// from the current frame.
+  Closure(usize),
+  SetGlobal(usize),
   Add,
   Subtract,
   Multiply,
   Divide,
   Modulo,
+  Power,
+  Negate,
+  BitAnd,
+  BitOr,
+  BitXor,
+  BitNot,
+  ShiftLeft,
+  ShiftRight,
+  ShiftRightUnsigned,
   Equal,
   Not,
   Greater,
   Less,
-  And,
-  Or,
+  Pop,
+  /// Removes the given number of values below the top of the stack, as
+  /// when the bindings of a `let` go out of scope.
+  PopBelow(usize),
+  /// Jumps to the given code index unconditionally.
+  Jump(usize),
+  /// Jumps to the given code index if the top of the stack is falsey,
+  /// leaving it on the stack.
+  JumpIfFalse(usize),
+  /// Jumps to the given code index if the top of the stack is truthy,
+  /// leaving it on the stack.
+  JumpIfTrue(usize),
+  /// Jumps back to the given code index, counting one loop iteration
+  /// against the VM's budget.
+  Loop(usize),
+  /// Pushes the next element of the iterable in the given slot, whose
+  /// position is kept in the slot above it, or jumps to the given code index
+  /// once there are none left.
+  IterNext(usize, usize),
   Call(u8),
+  /// Collects the given number of values from the top of the stack into a
+  /// list.
+  BuildList(usize),
+  /// Indexes the list or string below the index on top of the stack.
+  Index,
+  /// Slices the list or string on the stack; each flag says whether that
+  /// bound was written and so sits above it on the stack.
+  Slice(bool, bool),
+  /// Converts the value on top of the stack to the units or currency of a
+  /// constant.
+  Convert(usize),
+  /// Replaces the bounds on top of the stack with a range between them;
+  /// the flags say whether it is inclusive and whether a step sits above.
+  Range(bool, bool),
   Return,
+}
+
+impl Opcode {
+  /// How many values the opcode leaves on the stack, less the number it
+  /// takes off.
+  pub fn stack_effect(&self) -> isize {
+    match *self {
+      Opcode::Constant(_) | Opcode::GetGlobal(_) | Opcode::GetLocal(_) | Opcode::GetUpvalue(_) | Opcode::Closure(_) => 1,
+      Opcode::SetGlobal(_) | Opcode::SetLocal(_) | Opcode::Negate | Opcode::BitNot | Opcode::Not | Opcode::Convert(_) => 0,
+      Opcode::Jump(_) | Opcode::JumpIfFalse(_) | Opcode::JumpIfTrue(_) | Opcode::Loop(_) => 0,
+      Opcode::IterNext(..) => 1,
+      Opcode::Add | Opcode::Subtract | Opcode::Multiply | Opcode::Divide | Opcode::Modulo | Opcode::Power => -1,
+      Opcode::BitAnd | Opcode::BitOr | Opcode::BitXor | Opcode::ShiftLeft | Opcode::ShiftRight | Opcode::ShiftRightUnsigned => -1,
+      Opcode::Equal | Opcode::Greater | Opcode::Less | Opcode::Index | Opcode::Pop | Opcode::Return => -1,
+      Opcode::PopBelow(count) => -(count as isize),
+      Opcode::Range(_, has_step) => -1 - has_step as isize,
+      Opcode::Call(count) => -(count as isize),
+      Opcode::BuildList(count) => 1 - count as isize,
+      Opcode::Slice(has_start, has_end) => -(has_start as isize + has_end as isize),
+    }
+  }
 }
\ No newline at end of file
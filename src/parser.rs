@@ -1,16 +1,35 @@
-use crate::ast::{BinaryOperator, Expr, Program, Stmt};
+use crate::ast::{BinaryOperator, Expr, ExprKind, Program, SeriesOp, Stmt, UnaryOperator};
+use crate::bigint::BigInt;
+use crate::currency::Currency;
+use crate::decimal::Decimal;
+use crate::error::{Error, Result};
+use crate::lexer;
+use crate::rational::Rational;
+use crate::span::Span;
 use crate::token::{Token, TokenKind};
+use crate::units::{self, Quantity};
+
+/// Longest whole number a literal such as `1e30` may expand to, in digits.
+const MAX_LITERAL_DIGITS: usize = 1000;
 
 #[derive(Debug, PartialEq, PartialOrd)]
 pub enum Precedence {
   None,
+  Conversion,
+  Ternary,
+  Range,
   Or,
   And,
   Equality,
   Comparison,
+  BitOr,
+  BitXor,
+  BitAnd,
+  Shift,
   Term,
   Factor,
   Unary,
+  Power,
   Grouping,
   Call,
 }
@@ -18,13 +37,21 @@ pub enum Precedence {
 impl Precedence {
   pub fn from(kind: TokenKind) -> Precedence {
     match kind {
+      TokenKind::In | TokenKind::To => Precedence::Conversion,
+      TokenKind::Question => Precedence::Ternary,
+      TokenKind::DotDot | TokenKind::DotDotEqual => Precedence::Range,
       TokenKind::PipePipe => Precedence::Or,
       TokenKind::AmpAmp => Precedence::And,
       TokenKind::EqualEqual | TokenKind::BangEqual => Precedence::Equality,
       TokenKind::Greater | TokenKind::GreaterEqual | TokenKind::Less | TokenKind::LessEqual => Precedence::Comparison,
+      TokenKind::Pipe => Precedence::BitOr,
+      TokenKind::Xor => Precedence::BitXor,
+      TokenKind::Amp => Precedence::BitAnd,
+      TokenKind::LessLess | TokenKind::GreaterGreater | TokenKind::GreaterGreaterGreater => Precedence::Shift,
       TokenKind::Plus | TokenKind::Minus => Precedence::Term,
       TokenKind::Slash | TokenKind::Star | TokenKind::Percent => Precedence::Factor,
-      TokenKind::LeftParen => Precedence::Grouping,
+      TokenKind::Caret | TokenKind::StarStar => Precedence::Power,
+      TokenKind::LeftParen | TokenKind::LeftBracket => Precedence::Grouping,
       _ => Precedence::None,
     }
   }
@@ -35,6 +62,9 @@ pub struct Parser<'a> {
   tokens: Vec<Token<'a>>,
   curr: usize,
   next: usize,
+  /// Cleared while parsing `let` initializers, where `in` ends the
+  /// bindings rather than starting a conversion.
+  allow_in: bool,
 }
 
 impl <'a> Parser<'a> {
@@ -43,6 +73,7 @@ impl <'a> Parser<'a> {
       tokens,
       curr: 0,
       next: 1,
+      allow_in: true,
     }
   }
 
@@ -73,41 +104,225 @@ impl <'a> Parser<'a> {
     token.clone()
   }
 
-  fn parse_boolean_expr(&mut self) -> Option<Expr> {
-    match self.peek().kind {
-      TokenKind::Boolean => {
-        Some(Expr::Boolean(self.peek().slice == "true"))
-      }
-      _ => None,
+  fn error<T>(&self, message: String, span: Span) -> Result<T> {
+    Err(Error::Parse(message, span))
+  }
+
+  fn unexpected<T>(&self, token: &Token<'a>) -> Result<T> {
+    match token.kind {
+      TokenKind::Eof => self.error("unexpected end of input".to_string(), token.span),
+      TokenKind::Newline => self.error("unexpected end of line".to_string(), token.span),
+      _ => self.error(format!("unexpected token '{}'", token.slice), token.span),
     }
   }
 
-  fn parse_number_expr(&mut self) -> Option<Expr> {
-    match self.peek().kind {
-      TokenKind::Number => {
-        let value = self.peek().slice.parse::<f64>().unwrap();
-        Some(Expr::Number(value))
+  fn consume(&mut self, kind: TokenKind, message: &str) -> Result<Token<'a>> {
+    if self.next_peek().kind != kind {
+      return self.error(message.to_string(), self.next_peek().span);
+    }
+
+    self.advance();
+
+    Ok(self.peek())
+  }
+
+  fn parse_string_expr(&mut self) -> Result<Expr> {
+    let token = self.peek();
+    let contents = lexer::unescape(&token.slice[1..token.slice.len() - 1]).unwrap_or_default();
+
+    Ok(Expr::new(ExprKind::String(contents), token.span))
+  }
+
+  fn parse_boolean_expr(&mut self) -> Result<Expr> {
+    let token = self.peek();
+
+    Ok(Expr::new(ExprKind::Boolean(token.slice == "true"), token.span))
+  }
+
+  fn integer_kind(value: BigInt) -> ExprKind {
+    match value.to_i64() {
+      Some(value) => ExprKind::Integer(value),
+      None => ExprKind::BigInt(value),
+    }
+  }
+
+  fn parse_number_expr(&mut self) -> Result<Expr> {
+    let token = self.peek();
+    let digits = token.slice.replace('_', "");
+
+    let radix = match digits.get(..2) {
+      Some("0x" | "0X") => 16,
+      Some("0b" | "0B") => 2,
+      Some("0o" | "0O") => 8,
+      _ => 10,
+    };
+
+    // Literals are always exact: whole numbers grow to big integers as
+    // needed, and fractions must fit in 64 bits.
+    let kind = if radix != 10 {
+      BigInt::parse(&digits[2..], radix).map(Parser::integer_kind)
+    } else if digits.contains(['.', 'e', 'E']) {
+      match Rational::from_decimal_str(&digits) {
+        Some(value) if value.is_integer() => Some(ExprKind::Integer(value.numer())),
+        Some(value) => Some(ExprKind::Rational(value)),
+        None => BigInt::from_decimal_str(&digits, MAX_LITERAL_DIGITS).map(Parser::integer_kind),
       }
-      _ => None,
+    } else {
+      BigInt::parse(&digits, radix).map(Parser::integer_kind)
+    };
+
+    match kind {
+      Some(kind) => Ok(Expr::new(kind, token.span)),
+      None => self.error(format!("number literal '{}' is out of range", token.slice), token.span),
     }
   }
 
-  fn parse_unary_op_expr(&mut self) -> Option<Expr> {
-    None
+  fn parse_decimal_expr(&mut self) -> Result<Expr> {
+    let token = self.peek();
+
+    match Decimal::parse(&token.slice.replace('_', "")) {
+      Some(value) => Ok(Expr::new(ExprKind::Decimal(value), token.span)),
+      None => self.error(format!("decimal literal '{}' is out of range", token.slice), token.span),
+    }
   }
 
-  fn parse_binary_op_expr(&mut self, left: Option<Expr>) -> Option<Expr> {
-    let left = match left {
-      Some(expr) => expr,
-      None => return None,
+  fn parse_imaginary_expr(&mut self) -> Result<Expr> {
+    let token = self.peek();
+    let digits = token.slice[..token.slice.len() - 1].replace('_', "");
+
+    match digits.parse::<f64>() {
+      Ok(value) if value.is_finite() => Ok(Expr::new(ExprKind::Imaginary(value), token.span)),
+      _ => self.error(format!("number literal '{}' is out of range", token.slice), token.span),
+    }
+  }
+
+  fn is_unit(token: &Token) -> bool {
+    matches!(token.kind, TokenKind::Identifier | TokenKind::In) && units::lookup(token.slice).is_some()
+  }
+
+  fn parse_unit_factor(&mut self) -> Result<Quantity> {
+    self.advance();
+    let token = self.peek();
+
+    let unit = match (&token.kind, units::lookup(token.slice)) {
+      (TokenKind::Identifier | TokenKind::In, Some(unit)) => Quantity::unit(unit),
+      (TokenKind::Identifier, None) => return self.error(format!("unknown unit '{}'", token.slice), token.span),
+      _ => return self.unexpected(&token),
     };
 
+    if self.next_peek().kind != TokenKind::Caret {
+      return Ok(unit);
+    }
+
+    self.advance();
+
+    let negative = self.next_peek().kind == TokenKind::Minus;
+
+    if negative {
+      self.advance();
+    }
+
+    let exponent = self.consume(TokenKind::Number, "expected a whole number exponent")?;
+
+    match exponent.slice.parse::<i32>() {
+      Ok(exp) => Ok(unit.powi(if negative { -exp } else { exp })),
+      Err(_) => self.error("expected a whole number exponent".to_string(), exponent.span),
+    }
+  }
+
+  /// Parses a unit product such as `km`, `m/s^2` or `kW*h` starting at the
+  /// next token.
+  fn parse_unit(&mut self) -> Result<(Quantity, Span)> {
+    let start = self.next_peek().span;
+    let mut unit = self.parse_unit_factor()?;
+
+    while matches!(self.next_peek().kind, TokenKind::Star | TokenKind::Slash)
+      && self.tokens.get(self.next + 1).is_some_and(Parser::is_unit)
+    {
+      self.advance();
+
+      let divide = self.peek().kind == TokenKind::Slash;
+      let factor = self.parse_unit_factor()?;
+
+      unit = if divide { unit.div(&factor) } else { unit.mul(&factor) };
+    }
+
+    Ok((unit, start.to(self.peek().span)))
+  }
+
+  /// Attaches a unit written after a number, so `5 km` is `5 * (1 km)`.
+  fn parse_unit_suffix(&mut self, value: Expr) -> Result<Expr> {
+    let next = self.next_peek();
+
+    let is_suffix = match next.kind {
+      TokenKind::Identifier => Parser::is_unit(&next) || Currency::parse(next.slice).is_some(),
+      // `5 in` is five inches, but `5 in km` is a conversion.
+      TokenKind::In => self.allow_in && !matches!(self.tokens.get(self.next + 1), Some(token) if token.kind == TokenKind::Identifier),
+      _ => false,
+    };
+
+    if !is_suffix {
+      return Ok(value);
+    }
+
+    if let (false, Some(currency)) = (Parser::is_unit(&next), Currency::parse(next.slice)) {
+      self.advance();
+
+      let span = value.span.to(next.span);
+      let currency = Expr::new(ExprKind::Currency(currency), next.span);
+
+      return Ok(Expr::new(ExprKind::BinaryOp(Box::new(value), BinaryOperator::Multiply, Box::new(currency)), span));
+    }
+
+    let (unit, unit_span) = self.parse_unit()?;
+    let span = value.span.to(unit_span);
+    let unit = Expr::new(ExprKind::Unit(unit), unit_span);
+
+    Ok(Expr::new(ExprKind::BinaryOp(Box::new(value), BinaryOperator::Multiply, Box::new(unit)), span))
+  }
+
+  fn parse_convert_expr(&mut self, value: Expr) -> Result<Expr> {
+    let next = self.next_peek();
+
+    if let (TokenKind::Identifier, false, Some(currency)) = (&next.kind, Parser::is_unit(&next), Currency::parse(next.slice)) {
+      self.advance();
+
+      let span = value.span.to(next.span);
+      return Ok(Expr::new(ExprKind::ConvertCurrency(Box::new(value), currency), span));
+    }
+
+    let (unit, unit_span) = self.parse_unit()?;
+    let span = value.span.to(unit_span);
+
+    Ok(Expr::new(ExprKind::Convert(Box::new(value), unit), span))
+  }
+
+  fn parse_unary_op_expr(&mut self) -> Result<Expr> {
+    let token = self.peek();
+
+    let op = match token.kind {
+      TokenKind::Minus => UnaryOperator::Negative,
+      TokenKind::Bang => UnaryOperator::Not,
+      TokenKind::Tilde => UnaryOperator::BitNot,
+      _ => return self.unexpected(&token),
+    };
+
+    self.advance();
+
+    let operand = self.parse_expr(Precedence::Unary)?;
+    let span = token.span.to(operand.span);
+
+    Ok(Expr::new(ExprKind::UnaryOp(op, Box::new(operand)), span))
+  }
+
+  fn parse_binary_op_expr(&mut self, left: Expr) -> Result<Expr> {
     let op = match self.peek().kind {
       TokenKind::Plus => BinaryOperator::Add,
       TokenKind::Minus => BinaryOperator::Subtract,
       TokenKind::Star => BinaryOperator::Multiply,
       TokenKind::Slash => BinaryOperator::Divide,
       TokenKind::Percent => BinaryOperator::Modulo,
+      TokenKind::Caret | TokenKind::StarStar => BinaryOperator::Power,
       TokenKind::EqualEqual => BinaryOperator::Equal,
       TokenKind::BangEqual => BinaryOperator::NotEqual,
       TokenKind::Greater => BinaryOperator::GreaterThan,
@@ -116,116 +331,342 @@ impl <'a> Parser<'a> {
       TokenKind::LessEqual => BinaryOperator::LessThanOrEqual,
       TokenKind::AmpAmp => BinaryOperator::And,
       TokenKind::PipePipe => BinaryOperator::Or,
-      _ => return None,
+      TokenKind::Amp => BinaryOperator::BitAnd,
+      TokenKind::Pipe => BinaryOperator::BitOr,
+      TokenKind::Xor => BinaryOperator::BitXor,
+      TokenKind::LessLess => BinaryOperator::ShiftLeft,
+      TokenKind::GreaterGreater => BinaryOperator::ShiftRight,
+      TokenKind::GreaterGreaterGreater => BinaryOperator::ShiftRightUnsigned,
+      _ => return self.unexpected(&self.peek()),
     };
 
-    let precedence = Precedence::from(self.peek().kind);
+    let precedence = match Precedence::from(self.peek().kind) {
+      // Parsing the right operand one level lower lets a following `^`
+      // bind to it first, making power right-associative.
+      Precedence::Power => Precedence::Unary,
+      precedence => precedence,
+    };
 
     self.advance();
 
-    match self.parse_expr(precedence) {
-      Some(right) => {
-        Some(Expr::BinaryOp(Box::new(left), op, Box::new(right)))
-      },
-      None => None,
-    }
+    let right = self.parse_expr(precedence)?;
+    let span = left.span.to(right.span);
+
+    Ok(Expr::new(ExprKind::BinaryOp(Box::new(left), op, Box::new(right)), span))
+  }
+
+  /// Parses an expression inside brackets, where `in` is always allowed.
+  fn parse_nested_expr(&mut self) -> Result<Expr> {
+    let allow_in = std::mem::replace(&mut self.allow_in, true);
+    let expr = self.parse_expr(Precedence::None);
+
+    self.allow_in = allow_in;
+    expr
   }
 
-  fn parse_grouping_expr(&mut self) -> Option<Expr> {
+  fn parse_grouping_expr(&mut self) -> Result<Expr> {
+    let start = self.advance().span;
+
+    let mut expr = self.parse_nested_expr()?;
+
+    let end = self.consume(TokenKind::RightParen, "expected ')' after expression")?.span;
+    expr.span = start.to(end);
+
+    Ok(expr)
+  }
+
+  fn parse_range_expr(&mut self, start: Expr) -> Result<Expr> {
+    let inclusive = self.peek().kind == TokenKind::DotDotEqual;
+
     self.advance();
 
-    let expr = match self.parse_expr(Precedence::None) {
-      Some(expr) => expr,
-      None => return None,
+    let end = self.parse_expr(Precedence::Range)?;
+    let mut span = start.span.to(end.span);
+
+    // `step` is only a keyword here, so it stays usable as a name.
+    let step = match self.next_peek() {
+      token if token.kind == TokenKind::Identifier && token.slice == "step" => {
+        self.advance();
+        self.advance();
+
+        let step = self.parse_expr(Precedence::Range)?;
+        span = span.to(step.span);
+        Some(Box::new(step))
+      }
+      _ => None,
     };
 
+    Ok(Expr::new(ExprKind::Range(Box::new(start), Box::new(end), step, inclusive), span))
+  }
+
+  fn parse_ternary_expr(&mut self, condition: Expr) -> Result<Expr> {
+    self.advance();
+
+    let then_branch = self.parse_expr(Precedence::None)?;
+
+    self.consume(TokenKind::Colon, "expected ':' in conditional expression")?;
+    self.advance();
+
+    let else_branch = self.parse_expr(Precedence::None)?;
+    let span = condition.span.to(else_branch.span);
+
+    Ok(Expr::new(ExprKind::If(Box::new(condition), Box::new(then_branch), Box::new(else_branch)), span))
+  }
+
+  fn parse_if_expr(&mut self) -> Result<Expr> {
+    let start = self.peek().span;
+
+    self.advance();
+
+    let condition = self.parse_expr(Precedence::None)?;
+
+    self.consume(TokenKind::Then, "expected 'then' after condition")?;
+    self.advance();
+
+    let then_branch = self.parse_expr(Precedence::None)?;
+
+    self.consume(TokenKind::Else, "expected 'else' after 'then' branch")?;
+    self.advance();
+
+    let else_branch = self.parse_expr(Precedence::None)?;
+    let span = start.to(else_branch.span);
+
+    Ok(Expr::new(ExprKind::If(Box::new(condition), Box::new(then_branch), Box::new(else_branch)), span))
+  }
+
+  fn parse_let_expr(&mut self) -> Result<Expr> {
+    let start = self.peek().span;
+    let allow_in = std::mem::replace(&mut self.allow_in, false);
+    let mut bindings = Vec::new();
+
+    loop {
+      let name = self.consume(TokenKind::Identifier, "expected a variable name")?.slice.to_string();
+
+      self.consume(TokenKind::Equal, "expected '=' after variable name")?;
+      self.advance();
+
+      bindings.push((name, self.parse_expr(Precedence::None)?));
+
+      if self.next_peek().kind != TokenKind::Comma {
+        break;
+      }
+
+      self.advance();
+    }
+
+    self.allow_in = allow_in;
+
+    self.consume(TokenKind::In, "expected 'in' after let bindings")?;
     self.advance();
 
-    Some(expr)
+    let body = self.parse_expr(Precedence::None)?;
+    let span = start.to(body.span);
+
+    Ok(Expr::new(ExprKind::Let(bindings, Box::new(body)), span))
   }
 
-  fn parse_expr_list(&mut self, end_token_kind: TokenKind) -> Option<Vec<Expr>> {
+  fn parse_expr_list(&mut self, end_token_kind: TokenKind) -> Result<Vec<Expr>> {
     let mut exprs = Vec::new();
 
     if self.next_peek().kind == end_token_kind {
       self.advance();
-      return Some(exprs);
+      return Ok(exprs);
     }
 
     self.advance();
 
-    match self.parse_expr(Precedence::None) {
-      Some(expr) => {
-        exprs.push(expr);
-      },
-      None => return None,
-    };
+    exprs.push(self.parse_nested_expr()?);
 
     while self.next_peek().kind == TokenKind::Comma {
       self.advance();
       self.advance();
 
-      match self.parse_expr(Precedence::None) {
-        Some(expr) => {
-          exprs.push(expr);
-        },
-        None => return None,
-      };
+      exprs.push(self.parse_nested_expr()?);
     }
 
     if self.next_peek().kind != end_token_kind {
-      return None;
+      return self.unexpected(&self.next_peek());
     }
 
     self.advance();
 
-    Some(exprs)
+    Ok(exprs)
+  }
+
+  /// Parses `sum(k in iterable, body)` or `product(...)` after its `(`.
+  fn parse_series_expr(&mut self, callee: Expr, op: SeriesOp) -> Result<Expr> {
+    self.advance();
+
+    let name = self.peek().slice.to_string();
+
+    self.advance();
+    self.advance();
+
+    let iterable = self.parse_nested_expr()?;
+
+    self.consume(TokenKind::Comma, "expected ',' after series range")?;
+    self.advance();
+
+    let body = self.parse_nested_expr()?;
+    let end = self.consume(TokenKind::RightParen, "expected ')' after series body")?.span;
+
+    Ok(Expr::new(ExprKind::Series(op, name, Box::new(iterable), Box::new(body)), callee.span.to(end)))
+  }
+
+  fn parse_call_expr(&mut self, left: Expr) -> Result<Expr> {
+    let op = match &left.kind {
+      ExprKind::Identifier(name) if name == "sum" => Some(SeriesOp::Sum),
+      ExprKind::Identifier(name) if name == "product" => Some(SeriesOp::Product),
+      _ => None,
+    };
+
+    let is_series = self.next_peek().kind == TokenKind::Identifier
+      && self.tokens.get(self.next + 1).is_some_and(|token| token.kind == TokenKind::In);
+
+    if let (Some(op), true) = (op, is_series) {
+      return self.parse_series_expr(left, op);
+    }
+
+    let args = self.parse_expr_list(TokenKind::RightParen)?;
+    let span = left.span.to(self.peek().span);
+
+    Ok(Expr::new(ExprKind::Call(Box::new(left), args), span))
+  }
+
+  fn parse_list_expr(&mut self) -> Result<Expr> {
+    let start = self.peek().span;
+    let items = self.parse_expr_list(TokenKind::RightBracket)?;
+
+    Ok(Expr::new(ExprKind::List(items), start.to(self.peek().span)))
   }
 
-  fn parse_call_expr(&mut self, left: Option<Expr>) -> Option<Expr> {
-    let left = match left {
-      Some(expr) => expr,
-      None => return None,
+  /// Parses `[index]` or `[start:end]` after a value, with either bound of
+  /// a slice optional.
+  fn parse_index_expr(&mut self, left: Expr) -> Result<Expr> {
+    let left_span = left.span;
+    let start = match self.next_peek().kind {
+      TokenKind::Colon => None,
+      _ => {
+        self.advance();
+        Some(Box::new(self.parse_nested_expr()?))
+      }
     };
 
-    let args = match self.parse_expr_list(TokenKind::RightParen) {
-      Some(args) => args,
-      None => return None,
+    let kind = match (start, self.next_peek().kind) {
+      (Some(index), TokenKind::RightBracket) => ExprKind::Index(Box::new(left), index),
+      (start, TokenKind::Colon) => {
+        self.advance();
+
+        let end = match self.next_peek().kind {
+          TokenKind::RightBracket => None,
+          _ => {
+            self.advance();
+            Some(Box::new(self.parse_nested_expr()?))
+          }
+        };
+
+        ExprKind::Slice(Box::new(left), start, end)
+      }
+      _ => return self.unexpected(&self.next_peek()),
     };
 
-    Some(Expr::Call(Box::new(left), Box::new(args)))
+    self.consume(TokenKind::RightBracket, "expected ']'")?;
+
+    Ok(Expr::new(kind, left_span.to(self.peek().span)))
   }
 
-  fn parse_identifier(&mut self) -> Option<String> {
-    match self.peek().kind {
-      TokenKind::Identifier => Some(self.peek().slice.to_string()),
-      _ => None,
+  fn parse_identifier_expr(&mut self) -> Result<Expr> {
+    let token = self.peek();
+
+    Ok(Expr::new(ExprKind::Identifier(token.slice.to_string()), token.span))
+  }
+
+  /// Whether the tokens from `start` are a parameter list's names and
+  /// closing parenthesis, followed by `then`.
+  fn is_parameter_list(&self, start: usize, then: TokenKind) -> bool {
+    let params = &self.tokens[start.min(self.tokens.len())..];
+    let end = params.iter().position(|token| !matches!(token.kind, TokenKind::Identifier | TokenKind::Comma));
+
+    match end {
+      Some(end) => params[end].kind == TokenKind::RightParen && params.get(end + 1).is_some_and(|token| token.kind == then),
+      None => false,
     }
   }
 
-  fn parse_identifier_expr(&mut self) -> Option<Expr> {
-    match self.parse_identifier() {
-      Some(identifier) => Some(Expr::Identifier(identifier)),
-      None => None,
+  /// Parses the names of a parameter list whose `(` is the current token,
+  /// stopping at the `)`.
+  fn parse_params(&mut self) -> Result<Vec<String>> {
+    let mut params: Vec<String> = Vec::new();
+
+    while self.next_peek().kind != TokenKind::RightParen {
+      if !params.is_empty() {
+        self.consume(TokenKind::Comma, "expected ',' between parameters")?;
+      }
+
+      let param = self.consume(TokenKind::Identifier, "expected a parameter name")?;
+
+      if params.iter().any(|name| name == param.slice) {
+        return self.error(format!("duplicate parameter '{}'", param.slice), param.span);
+      }
+
+      params.push(param.slice.to_string());
     }
+
+    self.advance();
+
+    Ok(params)
+  }
+
+  fn parse_lambda_expr(&mut self) -> Result<Expr> {
+    let start = self.peek().span;
+
+    let params = match self.peek().kind {
+      TokenKind::LeftParen => self.parse_params()?,
+      _ => vec![self.peek().slice.to_string()],
+    };
+
+    self.consume(TokenKind::EqualGreater, "expected '=>' after parameters")?;
+    self.advance();
+
+    let body = self.parse_expr(Precedence::None)?;
+    let span = start.to(body.span);
+
+    Ok(Expr::new(ExprKind::Lambda(params, Box::new(body)), span))
   }
 
-  fn parse_expr(&mut self, precedence: Precedence) -> Option<Expr> {
+  fn parse_expr(&mut self, precedence: Precedence) -> Result<Expr> {
     let mut left = match self.peek().kind {
-      TokenKind::Number => self.parse_number_expr(),
-      TokenKind::Boolean => self.parse_boolean_expr(),
-      TokenKind::LeftParen => self.parse_grouping_expr(),
-      TokenKind::Identifier => self.parse_identifier_expr(),
-      _ => return None
+      TokenKind::Number => {
+        let value = self.parse_number_expr()?;
+        self.parse_unit_suffix(value)?
+      }
+      TokenKind::Decimal => {
+        let value = self.parse_decimal_expr()?;
+        self.parse_unit_suffix(value)?
+      }
+      TokenKind::Imaginary => self.parse_imaginary_expr()?,
+      TokenKind::Boolean => self.parse_boolean_expr()?,
+      TokenKind::String => self.parse_string_expr()?,
+      TokenKind::LeftParen if self.is_parameter_list(self.next, TokenKind::EqualGreater) => self.parse_lambda_expr()?,
+      TokenKind::LeftParen => self.parse_grouping_expr()?,
+      TokenKind::LeftBracket => self.parse_list_expr()?,
+      TokenKind::Identifier if self.next_peek().kind == TokenKind::EqualGreater => self.parse_lambda_expr()?,
+      TokenKind::Identifier => self.parse_identifier_expr()?,
+      TokenKind::Minus | TokenKind::Bang | TokenKind::Tilde => self.parse_unary_op_expr()?,
+      TokenKind::If => self.parse_if_expr()?,
+      TokenKind::Let => self.parse_let_expr()?,
+      _ => return self.unexpected(&self.peek()),
     };
 
-    while self.next_peek().kind != TokenKind::Eof && precedence < Precedence::from(self.next_peek().kind) {
+    while precedence < Precedence::from(self.next_peek().kind) {
       match self.next_peek().kind {
         TokenKind::Plus |
         TokenKind::Minus |
         TokenKind::Star |
         TokenKind::Slash |
         TokenKind::Percent |
+        TokenKind::Caret |
+        TokenKind::StarStar |
         TokenKind::EqualEqual |
         TokenKind::BangEqual |
         TokenKind::Greater |
@@ -233,61 +674,190 @@ impl <'a> Parser<'a> {
         TokenKind::Less |
         TokenKind::LessEqual |
         TokenKind::PipePipe |
-        TokenKind::AmpAmp => {
+        TokenKind::AmpAmp |
+        TokenKind::Pipe |
+        TokenKind::Xor |
+        TokenKind::Amp |
+        TokenKind::LessLess |
+        TokenKind::GreaterGreater |
+        TokenKind::GreaterGreaterGreater => {
           self.advance();
-          left = self.parse_binary_op_expr(left);
+          left = self.parse_binary_op_expr(left)?;
         }
         TokenKind::LeftParen => {
           self.advance();
-          left = self.parse_call_expr(left);
+          left = self.parse_call_expr(left)?;
+        }
+        TokenKind::LeftBracket => {
+          self.advance();
+          left = self.parse_index_expr(left)?;
+        }
+        TokenKind::Question => {
+          self.advance();
+          left = self.parse_ternary_expr(left)?;
+        }
+        TokenKind::DotDot | TokenKind::DotDotEqual => {
+          self.advance();
+          left = self.parse_range_expr(left)?;
+        }
+        TokenKind::In if !self.allow_in => return Ok(left),
+        TokenKind::In | TokenKind::To => {
+          self.advance();
+          left = self.parse_convert_expr(left)?;
         }
-        _ => return left,
+        _ => return Ok(left),
       }
     }
 
-    left
+    Ok(left)
   }
 
-  fn parse_expr_stmt(&mut self) -> Option<Stmt> {
-    match self.parse_expr(Precedence::None) {
-      Some(expr) => {
-        if self.next_peek().kind == TokenKind::Eof {
-          self.advance();
-        }
+  fn is_separator(kind: &TokenKind) -> bool {
+    matches!(kind, TokenKind::Semicolon | TokenKind::Newline)
+  }
 
-        Some(Stmt::Expr(expr))
-      },
-      None => None
+  fn is_end_of_stmt(kind: &TokenKind) -> bool {
+    Parser::is_separator(kind) || matches!(kind, TokenKind::RightBrace | TokenKind::Eof)
+  }
+
+  fn parse_end_of_stmt(&mut self) -> Result<()> {
+    if !Parser::is_end_of_stmt(&self.next_peek().kind) {
+      return self.unexpected(&self.next_peek());
     }
+
+    Ok(())
   }
 
-  fn parse_stmt(&mut self) -> Option<Stmt> {
-    match self.peek().kind {
-      _ => self.parse_expr_stmt()
+  fn parse_expr_stmt(&mut self) -> Result<Stmt> {
+    let expr = self.parse_expr(Precedence::None)?;
+
+    self.parse_end_of_stmt()?;
+
+    Ok(Stmt::Expr(expr))
+  }
+
+  fn parse_assign_stmt(&mut self) -> Result<Stmt> {
+    let name = self.peek().slice.to_string();
+
+    self.advance();
+    self.advance();
+
+    let value = self.parse_expr(Precedence::None)?;
+
+    self.parse_end_of_stmt()?;
+
+    Ok(Stmt::Assign(name, value))
+  }
+
+  fn parse_function_stmt(&mut self) -> Result<Stmt> {
+    let name = self.peek().slice.to_string();
+
+    self.advance();
+
+    let params = self.parse_params()?;
+
+    self.consume(TokenKind::Equal, "expected '=' after parameters")?;
+    self.advance();
+
+    let body = self.parse_expr(Precedence::None)?;
+
+    self.parse_end_of_stmt()?;
+
+    Ok(Stmt::Function(name, params, body))
+  }
+
+  /// Parses the statements of a block whose `{` is the current token,
+  /// stopping at the `}`.
+  fn parse_block(&mut self) -> Result<Vec<Stmt>> {
+    let start = self.peek().span;
+    let mut stmts = Vec::new();
+
+    loop {
+      self.advance();
+
+      match self.peek().kind {
+        TokenKind::Semicolon | TokenKind::Newline => {}
+        TokenKind::RightBrace => return Ok(stmts),
+        TokenKind::Eof => return self.error("expected '}' to close block".to_string(), start),
+        _ => stmts.push(self.parse_stmt()?),
+      }
+    }
+  }
+
+  fn parse_block_stmt(&mut self) -> Result<Stmt> {
+    let stmts = self.parse_block()?;
+
+    self.parse_end_of_stmt()?;
+
+    Ok(Stmt::Block(stmts))
+  }
+
+  fn parse_while_stmt(&mut self) -> Result<Stmt> {
+    self.advance();
+
+    let condition = self.parse_expr(Precedence::None)?;
+
+    self.consume(TokenKind::LeftBrace, "expected '{' after loop condition")?;
+
+    let body = self.parse_block()?;
+
+    self.parse_end_of_stmt()?;
+
+    Ok(Stmt::While(condition, body))
+  }
+
+  fn parse_for_stmt(&mut self) -> Result<Stmt> {
+    let name = self.consume(TokenKind::Identifier, "expected a loop variable")?.slice.to_string();
+
+    self.consume(TokenKind::In, "expected 'in' after loop variable")?;
+    self.advance();
+
+    let iterable = self.parse_expr(Precedence::None)?;
+
+    self.consume(TokenKind::LeftBrace, "expected '{' after loop iterable")?;
+
+    let body = self.parse_block()?;
+
+    self.parse_end_of_stmt()?;
+
+    Ok(Stmt::For(name, iterable, body))
+  }
+
+  fn parse_stmt(&mut self) -> Result<Stmt> {
+    match (self.peek().kind, self.next_peek().kind) {
+      (TokenKind::LeftBrace, _) => self.parse_block_stmt(),
+      (TokenKind::While, _) => self.parse_while_stmt(),
+      (TokenKind::For, _) => self.parse_for_stmt(),
+      (TokenKind::Identifier, TokenKind::Equal) => self.parse_assign_stmt(),
+      (TokenKind::Identifier, TokenKind::LeftParen) if self.is_parameter_list(self.curr + 2, TokenKind::Equal) => self.parse_function_stmt(),
+      _ => self.parse_expr_stmt(),
     }
   }
 
-  pub fn parse(&mut self) -> Program {
+  pub fn parse(&mut self) -> Result<Program> {
     let mut stmts = Program::new();
 
-    while !self.is_at_end() {
-      let stmt = self.parse_stmt();
-      if let Some(stmt) = stmt {
-        stmts.push(stmt);
+    while !self.is_at_end() && self.peek().kind != TokenKind::Eof {
+      if Parser::is_separator(&self.peek().kind) {
+        self.advance();
+        continue;
       }
 
+      stmts.push(self.parse_stmt()?);
       self.advance();
     }
 
-    stmts
+    Ok(stmts)
   }
 }
 
 #[cfg(test)]
 mod tests {
-  use crate::ast::{BinaryOperator, Expr, Stmt};
+  use crate::ast::{BinaryOperator, ExprKind, Stmt, UnaryOperator};
+  use crate::error::Error;
   use crate::lexer::Lexer;
   use crate::parser::Parser;
+  use crate::rational::Rational;
 
   #[test]
   fn parser() {
@@ -296,11 +866,11 @@ mod tests {
         "1 + 2",
         vec![
           Stmt::Expr(
-            Expr::BinaryOp(
-              Box::new(Expr::Number(1.0)),
+            ExprKind::BinaryOp(
+              Box::new(ExprKind::Integer(1).into()),
               BinaryOperator::Add,
-              Box::new(Expr::Number(2.0))
-            )
+              Box::new(ExprKind::Integer(2).into())
+            ).into()
           )
         ]
       ),
@@ -308,17 +878,17 @@ mod tests {
         "1 + 2 * 3",
         vec![
           Stmt::Expr(
-            Expr::BinaryOp(
-              Box::new(Expr::Number(1.0)),
+            ExprKind::BinaryOp(
+              Box::new(ExprKind::Integer(1).into()),
               BinaryOperator::Add,
               Box::new(
-                Expr::BinaryOp(
-                  Box::new(Expr::Number(2.0)),
+                ExprKind::BinaryOp(
+                  Box::new(ExprKind::Integer(2).into()),
                   BinaryOperator::Multiply,
-                  Box::new(Expr::Number(3.0))
-                )
+                  Box::new(ExprKind::Integer(3).into())
+                ).into()
               )
-            )
+            ).into()
           )
         ]
       ),
@@ -326,17 +896,17 @@ mod tests {
         "(1 + 2) * 3",
         vec![
           Stmt::Expr(
-            Expr::BinaryOp(
+            ExprKind::BinaryOp(
               Box::new(
-                Expr::BinaryOp(
-                  Box::new(Expr::Number(1.0)),
+                ExprKind::BinaryOp(
+                  Box::new(ExprKind::Integer(1).into()),
                   BinaryOperator::Add,
-                  Box::new(Expr::Number(2.0))
-                )
+                  Box::new(ExprKind::Integer(2).into())
+                ).into()
               ),
               BinaryOperator::Multiply,
-              Box::new(Expr::Number(3.0))
-            )
+              Box::new(ExprKind::Integer(3).into())
+            ).into()
           )
         ]
       ),
@@ -344,23 +914,23 @@ mod tests {
         "1 + 2 * 3 + 4",
         vec![
           Stmt::Expr(
-            Expr::BinaryOp(
+            ExprKind::BinaryOp(
               Box::new(
-                Expr::BinaryOp(
-                  Box::new(Expr::Number(1.0)),
+                ExprKind::BinaryOp(
+                  Box::new(ExprKind::Integer(1).into()),
                   BinaryOperator::Add,
                   Box::new(
-                    Expr::BinaryOp(
-                      Box::new(Expr::Number(2.0)),
+                    ExprKind::BinaryOp(
+                      Box::new(ExprKind::Integer(2).into()),
                       BinaryOperator::Multiply,
-                      Box::new(Expr::Number(3.0))
-                    )
+                      Box::new(ExprKind::Integer(3).into())
+                    ).into()
                   )
-                )
+                ).into()
               ),
               BinaryOperator::Add,
-              Box::new(Expr::Number(4.0))
-            )
+              Box::new(ExprKind::Integer(4).into())
+            ).into()
           )
         ]
       ),
@@ -368,12 +938,144 @@ mod tests {
         "sin(1)",
         vec![
           Stmt::Expr(
-            Expr::Call(
-              Box::new(Expr::Identifier("sin".to_string())),
-              Box::new(vec![
-                Expr::Number(1.0)
-              ])
-            )
+            ExprKind::Call(
+              Box::new(ExprKind::Identifier("sin".to_string()).into()),
+              vec![
+                ExprKind::Integer(1).into()
+              ]
+            ).into()
+          )
+        ]
+      ),
+      (
+        "-2 * 3",
+        vec![
+          Stmt::Expr(
+            ExprKind::BinaryOp(
+              Box::new(ExprKind::UnaryOp(UnaryOperator::Negative, Box::new(ExprKind::Integer(2).into())).into()),
+              BinaryOperator::Multiply,
+              Box::new(ExprKind::Integer(3).into())
+            ).into()
+          )
+        ]
+      ),
+      (
+        "2 ^ 3 ** 2",
+        vec![
+          Stmt::Expr(
+            ExprKind::BinaryOp(
+              Box::new(ExprKind::Integer(2).into()),
+              BinaryOperator::Power,
+              Box::new(
+                ExprKind::BinaryOp(
+                  Box::new(ExprKind::Integer(3).into()),
+                  BinaryOperator::Power,
+                  Box::new(ExprKind::Integer(2).into())
+                ).into()
+              )
+            ).into()
+          )
+        ]
+      ),
+      (
+        "-2^2",
+        vec![
+          Stmt::Expr(
+            ExprKind::UnaryOp(
+              UnaryOperator::Negative,
+              Box::new(
+                ExprKind::BinaryOp(
+                  Box::new(ExprKind::Integer(2).into()),
+                  BinaryOperator::Power,
+                  Box::new(ExprKind::Integer(2).into())
+                ).into()
+              )
+            ).into()
+          )
+        ]
+      ),
+      (
+        "a ? 1 : b ? 2 : 3",
+        vec![
+          Stmt::Expr(
+            ExprKind::If(
+              Box::new(ExprKind::Identifier("a".to_string()).into()),
+              Box::new(ExprKind::Integer(1).into()),
+              Box::new(
+                ExprKind::If(
+                  Box::new(ExprKind::Identifier("b".to_string()).into()),
+                  Box::new(ExprKind::Integer(2).into()),
+                  Box::new(ExprKind::Integer(3).into())
+                ).into()
+              )
+            ).into()
+          )
+        ]
+      ),
+      (
+        "if a then 1 else 2",
+        vec![
+          Stmt::Expr(
+            ExprKind::If(
+              Box::new(ExprKind::Identifier("a".to_string()).into()),
+              Box::new(ExprKind::Integer(1).into()),
+              Box::new(ExprKind::Integer(2).into())
+            ).into()
+          )
+        ]
+      ),
+      (
+        "1 | 6 & 3 << 1 == 7",
+        vec![
+          Stmt::Expr(
+            ExprKind::BinaryOp(
+              Box::new(
+                ExprKind::BinaryOp(
+                  Box::new(ExprKind::Integer(1).into()),
+                  BinaryOperator::BitOr,
+                  Box::new(
+                    ExprKind::BinaryOp(
+                      Box::new(ExprKind::Integer(6).into()),
+                      BinaryOperator::BitAnd,
+                      Box::new(
+                        ExprKind::BinaryOp(
+                          Box::new(ExprKind::Integer(3).into()),
+                          BinaryOperator::ShiftLeft,
+                          Box::new(ExprKind::Integer(1).into())
+                        ).into()
+                      )
+                    ).into()
+                  )
+                ).into()
+              ),
+              BinaryOperator::Equal,
+              Box::new(ExprKind::Integer(7).into())
+            ).into()
+          )
+        ]
+      ),
+      (
+        "!-x",
+        vec![
+          Stmt::Expr(
+            ExprKind::UnaryOp(
+              UnaryOperator::Not,
+              Box::new(
+                ExprKind::UnaryOp(
+                  UnaryOperator::Negative,
+                  Box::new(ExprKind::Identifier("x".to_string()).into())
+                ).into()
+              )
+            ).into()
+          )
+        ]
+      ),
+      (
+        "rate = 0.07",
+        vec![
+          Stmt::Assign(
+            "rate".to_string(),
+            ExprKind::Rational(Rational::new(7, 100).unwrap()).into()
           )
         ]
       ),
@@ -381,17 +1083,109 @@ mod tests {
         "true && 10 > 1",
         vec![
           Stmt::Expr(
-            Expr::BinaryOp(
-              Box::new(Expr::Boolean(true)),
+            ExprKind::BinaryOp(
+              Box::new(ExprKind::Boolean(true).into()),
               BinaryOperator::And,
               Box::new(
-                Expr::BinaryOp(
-                  Box::new(Expr::Number(10.0)),
+                ExprKind::BinaryOp(
+                  Box::new(ExprKind::Integer(10).into()),
                   BinaryOperator::GreaterThan,
-                  Box::new(Expr::Number(1.0))
-                )
+                  Box::new(ExprKind::Integer(1).into())
+                ).into()
+              )
+            ).into()
+          )
+        ]
+      ),
+      (
+        "[1, x][0][:2]",
+        vec![
+          Stmt::Expr(
+            ExprKind::Slice(
+              Box::new(
+                ExprKind::Index(
+                  Box::new(
+                    ExprKind::List(vec![ExprKind::Integer(1).into(), ExprKind::Identifier("x".to_string()).into()]).into()
+                  ),
+                  Box::new(ExprKind::Integer(0).into())
+                ).into()
+              ),
+              None,
+              Some(Box::new(ExprKind::Integer(2).into()))
+            ).into()
+          )
+        ]
+      ),
+      (
+        "f(x, y) = x * y",
+        vec![
+          Stmt::Function(
+            "f".to_string(),
+            vec!["x".to_string(), "y".to_string()],
+            ExprKind::BinaryOp(
+              Box::new(ExprKind::Identifier("x".to_string()).into()),
+              BinaryOperator::Multiply,
+              Box::new(ExprKind::Identifier("y".to_string()).into())
+            ).into()
+          )
+        ]
+      ),
+      (
+        "map(xs, (x) => x + 1)",
+        vec![
+          Stmt::Expr(
+            ExprKind::Call(
+              Box::new(ExprKind::Identifier("map".to_string()).into()),
+              vec![
+                ExprKind::Identifier("xs".to_string()).into(),
+                ExprKind::Lambda(
+                  vec!["x".to_string()],
+                  Box::new(
+                    ExprKind::BinaryOp(
+                      Box::new(ExprKind::Identifier("x".to_string()).into()),
+                      BinaryOperator::Add,
+                      Box::new(ExprKind::Integer(1).into())
+                    ).into()
+                  )
+                ).into()
+              ]
+            ).into()
+          )
+        ]
+      ),
+      (
+        "x = 1;\nlet y = x in y",
+        vec![
+          Stmt::Assign("x".to_string(), ExprKind::Integer(1).into()),
+          Stmt::Expr(
+            ExprKind::Let(
+              vec![("y".to_string(), ExprKind::Identifier("x".to_string()).into())],
+              Box::new(ExprKind::Identifier("y".to_string()).into())
+            ).into()
+          )
+        ]
+      ),
+      (
+        "for i in 0..n {\n  t = t + i\n}",
+        vec![
+          Stmt::For(
+            "i".to_string(),
+            ExprKind::Range(
+              Box::new(ExprKind::Integer(0).into()),
+              Box::new(ExprKind::Identifier("n".to_string()).into()),
+              None,
+              false
+            ).into(),
+            vec![
+              Stmt::Assign(
+                "t".to_string(),
+                ExprKind::BinaryOp(
+                  Box::new(ExprKind::Identifier("t".to_string()).into()),
+                  BinaryOperator::Add,
+                  Box::new(ExprKind::Identifier("i".to_string()).into())
+                ).into()
               )
-            )
+            ]
           )
         ]
       )
@@ -400,10 +1194,57 @@ mod tests {
 
     for (input, expected) in testcases {
       let mut lexer = Lexer::new(input);
-      let mut parser = Parser::new(lexer.lex());
+      let mut parser = Parser::new(lexer.lex().unwrap());
 
       println!("{}", input);
-      assert_eq!(parser.parse(), expected);
+      assert_eq!(parser.parse().unwrap(), expected);
+    }
+  }
+
+  #[test]
+  fn errors() {
+    let testcases = vec![
+      "1 +",
+      "(1 + 2",
+      "1 2",
+      "sin(1, )",
+      "a ? 1",
+      "if a then 1",
+      "1e1000",
+      "1.5e-30",
+      "12345678901234567890.5",
+      "5 to 3",
+      "5 km to parsec",
+      "5 m^x",
+      "[1, 2",
+      "v[]",
+      "v[1:2:3]",
+      "v[1, 2]",
+      "f(x, x) = 1",
+      "f(x) =",
+      "(x, x) => x",
+      "x =>",
+      "1 2; 3",
+      "a = 1 b = 2",
+      "let x = 1",
+      "let x in x",
+      "let = 1 in 2",
+      "while x { x",
+      "for 1 in xs { x }",
+      "for x xs { x }",
+      "for x in xs { x } 1",
+      "}",
+      "1..",
+      "0..1 step",
+      "sum(k in 1..3)",
+      "sum(k in 1..3, k",
+    ];
+
+    for input in testcases {
+      let mut lexer = Lexer::new(input);
+      let mut parser = Parser::new(lexer.lex().unwrap());
+
+      assert!(matches!(parser.parse(), Err(Error::Parse(..))), "{}", input);
     }
   }
 }
\ No newline at end of file
@@ -0,0 +1,208 @@
+use std::fmt;
+use crate::ast::BinaryOperator;
+use crate::bigint::BigInt;
+use crate::decimal::DecimalContext;
+use crate::number;
+use crate::object::Object;
+
+/// Float ranges such as `0..1 step 0.1` count a step that falls within this
+/// much of the end as reaching it.
+const TOLERANCE: f64 = 1e-9;
+
+/// An arithmetic sequence written `start..end`, `start..=end` or
+/// `start..end step step`. Elements are computed when asked for, so even a
+/// huge range only holds its bounds.
+#[derive(Debug, Clone)]
+pub struct Range {
+  start: Object,
+  end: Object,
+  step: Object,
+  inclusive: bool,
+  len: BigInt,
+}
+
+impl Range {
+  pub fn new(start: Object, end: Object, step: Object, inclusive: bool, context: &DecimalContext) -> Result<Self, String> {
+    for bound in [&start, &end, &step] {
+      if !bound.is_real() {
+        return Err(format!("range bounds must be real numbers, got {}", bound.type_name()));
+      }
+    }
+
+    if number::equal(&step, &Object::Integer(0)) {
+      return Err("range step must not be zero".to_string());
+    }
+
+    // Exclusive ranges hold the elements strictly before `steps`, and
+    // inclusive ones also the element at it.
+    let len = match Range::exact_steps(&start, &end, &step) {
+      Some((floor, _)) if inclusive => floor.add(&BigInt::from_i64(1)),
+      Some((_, ceil)) => ceil,
+      None => {
+        let distance = number::arithmetic(&BinaryOperator::Subtract, &end, &start, context)?;
+        let steps = number::arithmetic(&BinaryOperator::Divide, &distance, &step, context)?;
+        let steps = steps.to_f64().unwrap_or(f64::NAN);
+        let len = if inclusive { (steps + TOLERANCE).floor() + 1.0 } else { (steps - TOLERANCE).ceil() };
+
+        match len {
+          len if len.is_nan() => BigInt::zero(),
+          len if len >= i64::MAX as f64 => return Err("range has too many elements".to_string()),
+          len => BigInt::from_i64(len as i64),
+        }
+      }
+    };
+
+    Ok(Self {
+      start,
+      end,
+      step,
+      inclusive,
+      len: if len.is_negative() { BigInt::zero() } else { len },
+    })
+  }
+
+  /// The floor and ceiling of `(end - start) / step` when all three are
+  /// exact, however large.
+  fn exact_steps(start: &Object, end: &Object, step: &Object) -> Option<(BigInt, BigInt)> {
+    let (start_num, start_den) = number::fraction(start)?;
+    let (end_num, end_den) = number::fraction(end)?;
+    let (step_num, step_den) = number::fraction(step)?;
+
+    let distance = end_num.mul(&start_den).sub(&start_num.mul(&end_den));
+    let mut numer = distance.mul(&step_den);
+    let mut denom = start_den.mul(&end_den).mul(&step_num);
+
+    if denom.is_negative() {
+      numer = numer.neg();
+      denom = denom.neg();
+    }
+
+    let (quotient, remainder) = numer.divmod(&denom)?;
+    let one = BigInt::from_i64(1);
+
+    Some(match (remainder.is_zero(), numer.is_negative()) {
+      (true, _) => (quotient.clone(), quotient),
+      (false, true) => (quotient.sub(&one), quotient),
+      (false, false) => (quotient.clone(), quotient.add(&one)),
+    })
+  }
+
+  /// The number of elements, which may be too many for a list.
+  pub fn len(&self) -> &BigInt {
+    &self.len
+  }
+
+  /// The number of elements if it fits in a `usize`.
+  pub fn count(&self) -> Option<usize> {
+    self.len.to_i64().and_then(|len| usize::try_from(len).ok())
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len.is_zero()
+  }
+
+  /// The element at the integer `index`, which must be less than the length.
+  pub fn get(&self, index: &Object, context: &DecimalContext) -> Result<Object, String> {
+    let offset = number::arithmetic(&BinaryOperator::Multiply, index, &self.step, context)?;
+    number::arithmetic(&BinaryOperator::Add, &self.start, &offset, context)
+  }
+
+  /// Like `get`, but counts from the end when `index` is negative and
+  /// checks that it is in range.
+  pub fn index(&self, index: &Object, context: &DecimalContext) -> Result<Object, String> {
+    let Some(big) = number::big(index) else {
+      return Err(format!("index must be an integer, got {}", index.type_name()));
+    };
+
+    let position = if big.is_negative() { big.add(&self.len) } else { big };
+
+    if position.is_negative() || position >= self.len {
+      return Err(format!("index {} is out of range for length {}", index, self.len));
+    }
+
+    self.get(&number::from_bigint(position), context)
+  }
+
+  pub fn to_list(&self, context: &DecimalContext) -> Result<Vec<Object>, String> {
+    let len = self.count().ok_or("range has too many elements")?;
+    (0..len).map(|index| self.get(&Object::Integer(index as i64), context)).collect()
+  }
+
+  /// Adds up the elements without visiting them, as `len * start` plus
+  /// `step` times the sum of `0..len`.
+  pub fn sum(&self, context: &DecimalContext) -> Result<Object, String> {
+    if self.is_empty() {
+      return Ok(Object::Integer(0));
+    }
+
+    let len = number::from_bigint(self.len.clone());
+    let pairs = number::from_bigint(self.len.mul(&self.len.sub(&BigInt::from_i64(1))));
+    let triangle = number::arithmetic(&BinaryOperator::Divide, &pairs, &Object::Integer(2), context)?;
+    let offsets = number::arithmetic(&BinaryOperator::Multiply, &triangle, &self.step, context)?;
+    let starts = number::arithmetic(&BinaryOperator::Multiply, &len, &self.start, context)?;
+
+    number::arithmetic(&BinaryOperator::Add, &starts, &offsets, context)
+  }
+}
+
+/// Ranges are equal when they hold the same elements, however they were
+/// written: `1..=3` equals `1..4`, and `1..3` equals `1..3.0`.
+impl PartialEq for Range {
+  fn eq(&self, other: &Self) -> bool {
+    let one = BigInt::from_i64(1);
+
+    self.len == other.len
+      && (self.len.is_zero() || number::equal(&self.start, &other.start))
+      && (self.len <= one || number::equal(&self.step, &other.step))
+  }
+}
+
+impl fmt::Display for Range {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}{}{}", self.start, if self.inclusive { "..=" } else { ".." }, self.end)?;
+
+    if !number::equal(&self.step, &Object::Integer(1)) {
+      write!(f, " step {}", self.step)?;
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::bigint::BigInt;
+  use crate::decimal::DecimalContext;
+  use crate::number;
+  use crate::object::Object;
+  use crate::range::Range;
+  use crate::rational::Rational;
+
+  #[test]
+  fn lengths() {
+    let context = DecimalContext::default();
+    let range = |start: i64, end: i64, step: Object, inclusive: bool| {
+      Range::new(Object::Integer(start), Object::Integer(end), step, inclusive, &context).unwrap()
+    };
+
+    assert_eq!(range(1, 10, Object::Integer(1), false).count(), Some(9));
+    assert_eq!(range(1, 10, Object::Integer(1), true).count(), Some(10));
+    assert_eq!(range(0, 10, Object::Integer(3), false).count(), Some(4));
+    assert_eq!(range(10, 0, Object::Integer(-2), true).count(), Some(6));
+    assert_eq!(range(0, 10, Object::Integer(-1), false).count(), Some(0));
+    assert_eq!(range(0, 1, Object::Rational(Rational::new(1, 10).unwrap()), false).count(), Some(10));
+    assert_eq!(range(0, 1, Object::Number(0.1), true).count(), Some(11));
+
+    let evens = range(0, 100, Object::Integer(2), true);
+    assert_eq!(evens.get(&Object::Integer(3), &context).unwrap(), Object::Integer(6));
+    assert_eq!(evens.sum(&context).unwrap(), Object::Integer(2550));
+    assert_eq!(range(5, 5, Object::Integer(1), false).sum(&context).unwrap(), Object::Integer(0));
+
+    assert!(Range::new(Object::Integer(0), Object::Integer(1), Object::Integer(0), false, &context).is_err());
+
+    let huge = Range::new(Object::Integer(0), number::from_bigint(BigInt::from_i128(10_i128.pow(30))), Object::Integer(1), false, &context).unwrap();
+    assert_eq!(huge.count(), None);
+    assert_eq!(huge.len().to_string(), "1000000000000000000000000000000");
+    assert!(huge.to_list(&context).is_err());
+  }
+}
@@ -0,0 +1,246 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+/// An exact fraction kept in lowest terms with a positive denominator.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Rational {
+  num: i64,
+  den: i64,
+}
+
+fn gcd(mut a: i128, mut b: i128) -> i128 {
+  while b != 0 {
+    (a, b) = (b, a % b);
+  }
+
+  a.abs()
+}
+
+impl Rational {
+  pub fn new(num: i64, den: i64) -> Option<Self> {
+    Rational::reduce(num as i128, den as i128)
+  }
+
+  pub fn from_integer(n: i64) -> Self {
+    Self {
+      num: n,
+      den: 1,
+    }
+  }
+
+  fn reduce(num: i128, den: i128) -> Option<Self> {
+    if den == 0 {
+      return None;
+    }
+
+    let divisor = gcd(num, den);
+    let sign = if den < 0 { -1 } else { 1 };
+    let num = i64::try_from(sign * num / divisor).ok()?;
+    let den = i64::try_from(sign * den / divisor).ok()?;
+
+    Some(Self {
+      num,
+      den,
+    })
+  }
+
+  /// Parses a decimal literal such as `0.1`, `2.5e3` or `1e-9` exactly.
+  /// Returns `None` if the value does not fit in a 64-bit fraction.
+  pub fn from_decimal_str(literal: &str) -> Option<Self> {
+    let (mantissa, exponent) = match literal.find(['e', 'E']) {
+      Some(index) => (&literal[..index], literal[index + 1..].parse::<i32>().ok()?),
+      None => (literal, 0),
+    };
+
+    let (whole, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let digits = format!("{}{}", whole, fraction);
+    let digits = digits.trim_start_matches('0');
+
+    if digits.len() > 36 {
+      return None;
+    }
+
+    let mut num = if digits.is_empty() { 0 } else { digits.parse::<i128>().ok()? };
+    let mut den: i128 = 1;
+    let scale = exponent.checked_sub(fraction.len() as i32)?;
+
+    if scale >= 0 {
+      num = num.checked_mul(10i128.checked_pow(scale as u32)?)?;
+    } else {
+      den = 10i128.checked_pow(scale.unsigned_abs())?;
+    }
+
+    Rational::reduce(num, den)
+  }
+
+  pub fn numer(&self) -> i64 {
+    self.num
+  }
+
+  pub fn denom(&self) -> i64 {
+    self.den
+  }
+
+  pub fn is_integer(&self) -> bool {
+    self.den == 1
+  }
+
+  pub fn to_f64(&self) -> f64 {
+    self.num as f64 / self.den as f64
+  }
+
+  pub fn checked_add(self, other: Self) -> Option<Self> {
+    let num = self.num as i128 * other.den as i128 + other.num as i128 * self.den as i128;
+    Rational::reduce(num, self.den as i128 * other.den as i128)
+  }
+
+  pub fn checked_sub(self, other: Self) -> Option<Self> {
+    let num = self.num as i128 * other.den as i128 - other.num as i128 * self.den as i128;
+    Rational::reduce(num, self.den as i128 * other.den as i128)
+  }
+
+  pub fn checked_mul(self, other: Self) -> Option<Self> {
+    Rational::reduce(self.num as i128 * other.num as i128, self.den as i128 * other.den as i128)
+  }
+
+  pub fn checked_div(self, other: Self) -> Option<Self> {
+    Rational::reduce(self.num as i128 * other.den as i128, self.den as i128 * other.num as i128)
+  }
+
+  /// Remainder with the sign of the dividend, matching `%` on floats.
+  pub fn checked_rem(self, other: Self) -> Option<Self> {
+    let quotient = self.checked_div(other)?.trunc();
+    self.checked_sub(other.checked_mul(Rational::from_integer(quotient))?)
+  }
+
+  pub fn checked_pow(self, exp: i64) -> Option<Self> {
+    let base = if exp < 0 { Rational::from_integer(1).checked_div(self)? } else { self };
+    let exp = u32::try_from(exp.unsigned_abs()).ok()?;
+
+    Some(Self {
+      num: base.num.checked_pow(exp)?,
+      den: base.den.checked_pow(exp)?,
+    })
+  }
+
+  pub fn checked_neg(self) -> Option<Self> {
+    Some(Self {
+      num: self.num.checked_neg()?,
+      den: self.den,
+    })
+  }
+
+  pub fn abs(self) -> Option<Self> {
+    if self.num < 0 { self.checked_neg() } else { Some(self) }
+  }
+
+  pub fn floor(&self) -> i64 {
+    self.num.div_euclid(self.den)
+  }
+
+  pub fn ceil(&self) -> i64 {
+    -(-self.num).div_euclid(self.den)
+  }
+
+  pub fn trunc(&self) -> i64 {
+    self.num / self.den
+  }
+
+  /// Rounds half away from zero, like `f64::round`.
+  pub fn round(&self) -> i64 {
+    let twice = 2 * self.num as i128 + self.num.signum() as i128 * self.den as i128;
+    (twice / (2 * self.den as i128)) as i64
+  }
+
+  fn write_decimal(&self, f: &mut fmt::Formatter, places: usize) -> fmt::Result {
+    let scale = match 10i128.checked_pow(places as u32) {
+      Some(scale) => scale,
+      None => return write!(f, "{:.*}", places, self.to_f64()),
+    };
+
+    let scaled = Rational::reduce(self.num as i128 * scale, self.den as i128);
+    let scaled = match scaled {
+      Some(scaled) => scaled.round().unsigned_abs() as u128,
+      None => return write!(f, "{:.*}", places, self.to_f64()),
+    };
+
+    let sign = if self.num < 0 && scaled != 0 { "-" } else { "" };
+    let whole = scaled / scale as u128;
+    let fraction = scaled % scale as u128;
+
+    match places {
+      0 => write!(f, "{}{}", sign, whole),
+      _ => write!(f, "{}{}.{:0width$}", sign, whole, fraction, width = places),
+    }
+  }
+
+  /// Number of decimal places needed to write the value exactly, if its
+  /// denominator has no prime factors other than 2 and 5.
+  fn terminating_places(&self) -> Option<usize> {
+    let mut den = self.den;
+    let (mut twos, mut fives) = (0, 0);
+
+    while den % 2 == 0 {
+      den /= 2;
+      twos += 1;
+    }
+
+    while den % 5 == 0 {
+      den /= 5;
+      fives += 1;
+    }
+
+    if den == 1 { Some(twos.max(fives)) } else { None }
+  }
+}
+
+impl PartialOrd for Rational {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Ord for Rational {
+  fn cmp(&self, other: &Self) -> Ordering {
+    (self.num as i128 * other.den as i128).cmp(&(other.num as i128 * self.den as i128))
+  }
+}
+
+/// Terminating fractions print as exact decimals (`3/10` as `0.3`), the
+/// rest as `num/den`. A precision (`{:.4}`) always prints a rounded decimal.
+impl fmt::Display for Rational {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    if let Some(places) = f.precision() {
+      return self.write_decimal(f, places);
+    }
+
+    match self.terminating_places() {
+      Some(places) if places <= 18 => self.write_decimal(f, places),
+      _ => write!(f, "{}/{}", self.num, self.den),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::rational::Rational;
+
+  #[test]
+  fn rational() {
+    let tenth = Rational::from_decimal_str("0.1").unwrap();
+    let fifth = Rational::from_decimal_str("0.2").unwrap();
+    let third = Rational::new(1, 3).unwrap();
+
+    assert_eq!(tenth.checked_add(fifth), Rational::from_decimal_str("0.3"));
+    assert_eq!(third.checked_mul(Rational::from_integer(3)), Some(Rational::from_integer(1)));
+    assert_eq!(Rational::from_decimal_str("2.5e3"), Some(Rational::from_integer(2500)));
+    assert_eq!(Rational::from_decimal_str("1e-9"), Rational::new(1, 1_000_000_000));
+    assert_eq!(Rational::new(-7, 2).unwrap().floor(), -4);
+    assert_eq!(Rational::new(-7, 2).unwrap().round(), -4);
+    assert_eq!(Rational::new(7, 2).unwrap().checked_rem(Rational::from_integer(2)), Rational::new(3, 2));
+
+    assert_eq!(third.to_string(), "1/3");
+    assert_eq!(Rational::new(-3, 8).unwrap().to_string(), "-0.375");
+    assert_eq!(format!("{:.4}", Rational::new(-2, 3).unwrap()), "-0.6667");
+  }
+}
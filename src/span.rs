@@ -0,0 +1,28 @@
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Span {
+  pub start: usize,
+  pub end: usize,
+  pub line: usize,
+  pub column: usize,
+}
+
+impl Span {
+  pub fn new(start: usize, end: usize, line: usize, column: usize) -> Self {
+    Self {
+      start,
+      end,
+      line,
+      column,
+    }
+  }
+
+  /// Returns a span covering `self` through the end of `other`.
+  pub fn to(self, other: Span) -> Span {
+    Span {
+      start: self.start,
+      end: other.end.max(self.end),
+      line: self.line,
+      column: self.column,
+    }
+  }
+}
@@ -1,24 +1,46 @@
+use crate::span::Span;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum TokenKind {
   LeftParen,
   RightParen,
+  LeftBracket,
+  RightBracket,
+  LeftBrace,
+  RightBrace,
 
   Comma,
+  Question,
+  Colon,
+  DotDot,
+  DotDotEqual,
+  Semicolon,
+  /// A line break that ends a statement; others are skipped as whitespace.
+  Newline,
 
   Plus,
   Minus,
   Star,
   Slash,
   Percent,
+  Caret,
+  StarStar,
 
   Bang,
   BangEqual,
   Equal,
   EqualEqual,
+  /// `=>`, between a lambda's parameters and its body.
+  EqualGreater,
   Greater,
   GreaterEqual,
   Less,
   LessEqual,
+  LessLess,
+  GreaterGreater,
+  GreaterGreaterGreater,
+
+  Tilde,
 
   Pipe,
   PipePipe,
@@ -27,9 +49,25 @@ pub enum TokenKind {
   AmpAmp,
 
   Number,
+  /// A fractional literal read in decimal mode.
+  Decimal,
+  /// A number with an `i` or `j` suffix, such as `3i`.
+  Imaginary,
   Boolean,
+  /// A double-quoted string literal; the slice keeps the quotes and escapes.
+  String,
   Identifier,
 
+  Let,
+  For,
+  While,
+  If,
+  Then,
+  Else,
+  Xor,
+  In,
+  To,
+
   Eof,
 }
 
@@ -37,13 +75,15 @@ pub enum TokenKind {
 pub struct Token<'a> {
   pub kind: TokenKind,
   pub slice: &'a str,
+  pub span: Span,
 }
 
 impl <'a> Token<'a> {
-  pub fn new(kind: TokenKind, slice: &'a str) -> Self {
+  pub fn new(kind: TokenKind, slice: &'a str, span: Span) -> Self {
     Self {
       kind,
       slice,
+      span,
     }
   }
 }
\ No newline at end of file
@@ -0,0 +1,352 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::rc::Rc;
+use crate::ast::BinaryOperator;
+use crate::object::Object;
+
+/// Exponents of the SI base dimensions: length, mass, time, electric
+/// current, temperature, amount of substance and luminous intensity.
+pub type Dimension = [i32; 7];
+
+const fn dim(length: i32, mass: i32, time: i32, current: i32, temperature: i32, amount: i32, luminosity: i32) -> Dimension {
+  [length, mass, time, current, temperature, amount, luminosity]
+}
+
+const LENGTH: Dimension = dim(1, 0, 0, 0, 0, 0, 0);
+const MASS: Dimension = dim(0, 1, 0, 0, 0, 0, 0);
+const TIME: Dimension = dim(0, 0, 1, 0, 0, 0, 0);
+const AREA: Dimension = dim(2, 0, 0, 0, 0, 0, 0);
+const VOLUME: Dimension = dim(3, 0, 0, 0, 0, 0, 0);
+const SPEED: Dimension = dim(1, 0, -1, 0, 0, 0, 0);
+const FREQUENCY: Dimension = dim(0, 0, -1, 0, 0, 0, 0);
+const FORCE: Dimension = dim(1, 1, -2, 0, 0, 0, 0);
+const ENERGY: Dimension = dim(2, 1, -2, 0, 0, 0, 0);
+const POWER: Dimension = dim(2, 1, -3, 0, 0, 0, 0);
+const PRESSURE: Dimension = dim(-1, 1, -2, 0, 0, 0, 0);
+const CURRENT: Dimension = dim(0, 0, 0, 1, 0, 0, 0);
+const CHARGE: Dimension = dim(0, 0, 1, 1, 0, 0, 0);
+const VOLTAGE: Dimension = dim(2, 1, -3, -1, 0, 0, 0);
+const RESISTANCE: Dimension = dim(2, 1, -3, -2, 0, 0, 0);
+const CAPACITANCE: Dimension = dim(-2, -1, 4, 2, 0, 0, 0);
+
+#[derive(Debug, PartialEq)]
+pub struct Unit {
+  pub name: &'static str,
+  /// Size of the unit in SI base units.
+  pub scale: f64,
+  pub dimension: Dimension,
+}
+
+macro_rules! unit {
+  ($name:expr, $scale:expr, $dimension:expr) => {
+    Unit { name: $name, scale: $scale, dimension: $dimension }
+  };
+}
+
+/// The built-in SI and imperial units. Temperatures are absolute (kelvin)
+/// only, since scales with an offset do not multiply like other units.
+pub const UNITS: &[Unit] = &[
+  unit!("m", 1.0, LENGTH),
+  unit!("km", 1e3, LENGTH),
+  unit!("cm", 1e-2, LENGTH),
+  unit!("mm", 1e-3, LENGTH),
+  unit!("um", 1e-6, LENGTH),
+  unit!("nm", 1e-9, LENGTH),
+  unit!("in", 0.0254, LENGTH),
+  unit!("ft", 0.3048, LENGTH),
+  unit!("yd", 0.9144, LENGTH),
+  unit!("mi", 1609.344, LENGTH),
+  unit!("nmi", 1852.0, LENGTH),
+  unit!("au", 1.495978707e11, LENGTH),
+  unit!("ly", 9.4607304725808e15, LENGTH),
+  unit!("kg", 1.0, MASS),
+  unit!("g", 1e-3, MASS),
+  unit!("mg", 1e-6, MASS),
+  unit!("t", 1e3, MASS),
+  unit!("lb", 0.45359237, MASS),
+  unit!("oz", 0.028349523125, MASS),
+  unit!("st", 6.35029318, MASS),
+  unit!("s", 1.0, TIME),
+  unit!("ms", 1e-3, TIME),
+  unit!("us", 1e-6, TIME),
+  unit!("ns", 1e-9, TIME),
+  unit!("min", 60.0, TIME),
+  unit!("h", 3600.0, TIME),
+  unit!("hr", 3600.0, TIME),
+  unit!("day", 86400.0, TIME),
+  unit!("week", 604800.0, TIME),
+  unit!("year", 31557600.0, TIME),
+  unit!("A", 1.0, CURRENT),
+  unit!("mA", 1e-3, CURRENT),
+  unit!("K", 1.0, dim(0, 0, 0, 0, 1, 0, 0)),
+  unit!("mol", 1.0, dim(0, 0, 0, 0, 0, 1, 0)),
+  unit!("cd", 1.0, dim(0, 0, 0, 0, 0, 0, 1)),
+  unit!("ha", 1e4, AREA),
+  unit!("acre", 4046.8564224, AREA),
+  unit!("L", 1e-3, VOLUME),
+  unit!("mL", 1e-6, VOLUME),
+  unit!("gal", 3.785411784e-3, VOLUME),
+  unit!("mph", 0.44704, SPEED),
+  unit!("kph", 1e3 / 3600.0, SPEED),
+  unit!("knot", 1852.0 / 3600.0, SPEED),
+  unit!("Hz", 1.0, FREQUENCY),
+  unit!("kHz", 1e3, FREQUENCY),
+  unit!("MHz", 1e6, FREQUENCY),
+  unit!("GHz", 1e9, FREQUENCY),
+  unit!("rpm", 1.0 / 60.0, FREQUENCY),
+  unit!("N", 1.0, FORCE),
+  unit!("kN", 1e3, FORCE),
+  unit!("lbf", 4.4482216152605, FORCE),
+  unit!("J", 1.0, ENERGY),
+  unit!("kJ", 1e3, ENERGY),
+  unit!("MJ", 1e6, ENERGY),
+  unit!("cal", 4.184, ENERGY),
+  unit!("kcal", 4184.0, ENERGY),
+  unit!("Wh", 3600.0, ENERGY),
+  unit!("kWh", 3.6e6, ENERGY),
+  unit!("eV", 1.602176634e-19, ENERGY),
+  unit!("BTU", 1055.05585262, ENERGY),
+  unit!("W", 1.0, POWER),
+  unit!("kW", 1e3, POWER),
+  unit!("MW", 1e6, POWER),
+  unit!("hp", 745.6998715822702, POWER),
+  unit!("Pa", 1.0, PRESSURE),
+  unit!("kPa", 1e3, PRESSURE),
+  unit!("MPa", 1e6, PRESSURE),
+  unit!("bar", 1e5, PRESSURE),
+  unit!("atm", 101325.0, PRESSURE),
+  unit!("psi", 6894.757293168, PRESSURE),
+  unit!("C", 1.0, CHARGE),
+  unit!("mAh", 3.6, CHARGE),
+  unit!("V", 1.0, VOLTAGE),
+  unit!("mV", 1e-3, VOLTAGE),
+  unit!("kV", 1e3, VOLTAGE),
+  unit!("ohm", 1.0, RESISTANCE),
+  unit!("F", 1.0, CAPACITANCE),
+];
+
+pub fn lookup(name: &str) -> Option<&'static Unit> {
+  UNITS.iter().find(|unit| unit.name == name)
+}
+
+/// A value expressed in some product of units, such as `4 km/h`. The scale
+/// and dimension are those of the unit product, so the value in SI base
+/// units is `value * scale`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quantity {
+  pub value: f64,
+  pub scale: f64,
+  pub dimension: Dimension,
+  units: Vec<(&'static str, i32)>,
+}
+
+impl Quantity {
+  /// A plain number, with no units.
+  pub fn scalar(value: f64) -> Self {
+    Self {
+      value,
+      scale: 1.0,
+      dimension: [0; 7],
+      units: Vec::new(),
+    }
+  }
+
+  /// One of `unit`.
+  pub fn unit(unit: &'static Unit) -> Self {
+    Self {
+      value: 1.0,
+      scale: unit.scale,
+      dimension: unit.dimension,
+      units: vec![(unit.name, 1)],
+    }
+  }
+
+  pub fn si_value(&self) -> f64 {
+    self.value * self.scale
+  }
+
+  pub fn is_dimensionless(&self) -> bool {
+    self.dimension == [0; 7]
+  }
+
+  pub fn with_value(&self, value: f64) -> Self {
+    Self {
+      value,
+      ..self.clone()
+    }
+  }
+
+  pub fn mul(&self, other: &Quantity) -> Self {
+    let mut units = self.units.clone();
+
+    for (name, exp) in &other.units {
+      match units.iter_mut().find(|(unit, _)| unit == name) {
+        Some((_, total)) => *total += exp,
+        None => units.push((name, *exp)),
+      }
+    }
+
+    units.retain(|(_, exp)| *exp != 0);
+
+    Self {
+      value: self.value * other.value,
+      scale: self.scale * other.scale,
+      dimension: std::array::from_fn(|i| self.dimension[i] + other.dimension[i]),
+      units,
+    }
+  }
+
+  pub fn div(&self, other: &Quantity) -> Self {
+    self.mul(&other.powi(-1))
+  }
+
+  pub fn powi(&self, exp: i32) -> Self {
+    Self {
+      value: self.value.powi(exp),
+      scale: self.scale.powi(exp),
+      dimension: self.dimension.map(|d| d * exp),
+      units: self.units.iter().map(|(name, e)| (*name, e * exp)).filter(|(_, e)| *e != 0).collect(),
+    }
+  }
+
+  /// Re-expresses the quantity in the units of `target`.
+  pub fn convert(&self, target: &Quantity) -> Result<Self, String> {
+    if self.dimension != target.dimension {
+      return Err(format!("cannot convert {} to {}", self.describe(), target.describe()));
+    }
+
+    Ok(target.with_value(self.si_value() / target.scale))
+  }
+
+  fn describe(&self) -> String {
+    if self.units.is_empty() { "a plain number".to_string() } else { self.unit_string() }
+  }
+
+  fn unit_string(&self) -> String {
+    let format = |units: Vec<(&str, i32)>| -> Vec<String> {
+      units.into_iter().map(|(name, exp)| if exp == 1 { name.to_string() } else { format!("{}^{}", name, exp) }).collect()
+    };
+
+    let numerator = format(self.units.iter().filter(|(_, exp)| *exp > 0).copied().collect());
+    let denominator = format(self.units.iter().filter(|(_, exp)| *exp < 0).map(|(name, exp)| (*name, -exp)).collect());
+
+    match (numerator.len(), denominator.len()) {
+      (_, 0) => numerator.join("*"),
+      (0, _) => format(self.units.clone()).join("*"),
+      (_, 1) => format!("{}/{}", numerator.join("*"), denominator[0]),
+      _ => format!("{}/({})", numerator.join("*"), denominator.join("*")),
+    }
+  }
+}
+
+impl fmt::Display for Quantity {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    fmt::Display::fmt(&self.value, f)?;
+    write!(f, " {}", self.unit_string())
+  }
+}
+
+fn quantity(value: &Object) -> Result<Quantity, String> {
+  match value {
+    Object::Quantity(q) => Ok(Quantity::clone(q)),
+    _ => match value.to_f64() {
+      Some(n) => Ok(Quantity::scalar(n)),
+      None => Err(format!("expected a real number or quantity, got {}", value.type_name())),
+    },
+  }
+}
+
+/// Wraps a quantity, collapsing one whose units cancel out to a number.
+pub fn from_quantity(value: Quantity) -> Object {
+  if value.is_dimensionless() {
+    Object::Number(value.si_value())
+  } else {
+    Object::Quantity(Rc::new(value))
+  }
+}
+
+/// Arithmetic where either operand has units. Sums and remainders take the
+/// units of the left operand and require both to have the same dimension.
+pub fn arithmetic(op: &BinaryOperator, left: &Object, right: &Object) -> Result<Object, String> {
+  let (left, right) = (quantity(left)?, quantity(right)?);
+
+  if matches!(op, BinaryOperator::Divide | BinaryOperator::Modulo) && right.value == 0.0 {
+    return Err("division by zero".to_string());
+  }
+
+  let result = match op {
+    BinaryOperator::Multiply => left.mul(&right),
+    BinaryOperator::Divide => left.div(&right),
+    BinaryOperator::Power => {
+      if !right.is_dimensionless() {
+        return Err(format!("exponent must not have units, got {}", right.describe()));
+      }
+
+      let exp = right.si_value();
+
+      if exp.fract() != 0.0 || exp.abs() > i32::MAX as f64 {
+        return Err(format!("a quantity can only be raised to a whole power, got {}", exp));
+      }
+
+      if left.value == 0.0 && exp < 0.0 {
+        return Err("division by zero".to_string());
+      }
+
+      left.powi(exp as i32)
+    },
+    BinaryOperator::Add | BinaryOperator::Subtract | BinaryOperator::Modulo => {
+      if left.dimension != right.dimension {
+        return Err(format!("incompatible units for '{}': {} and {}", op, left.describe(), right.describe()));
+      }
+
+      let right = right.si_value() / left.scale;
+
+      left.with_value(match op {
+        BinaryOperator::Add => left.value + right,
+        BinaryOperator::Subtract => left.value - right,
+        _ => left.value % right,
+      })
+    },
+    _ => unreachable!("{} is not arithmetic", op),
+  };
+
+  Ok(from_quantity(result))
+}
+
+pub fn convert(value: &Object, unit: &Quantity) -> Result<Object, String> {
+  Ok(Object::Quantity(Rc::new(quantity(value)?.convert(unit)?)))
+}
+
+/// Orders two values where either has units; values of different
+/// dimensions cannot be ordered.
+pub fn compare(left: &Object, right: &Object) -> Result<Option<Ordering>, String> {
+  let (left, right) = (quantity(left)?, quantity(right)?);
+
+  if left.dimension != right.dimension {
+    return Err(format!("cannot compare {} with {}", left.describe(), right.describe()));
+  }
+
+  Ok(left.si_value().partial_cmp(&right.si_value()))
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::units::{self, Quantity};
+
+  #[test]
+  fn quantity() {
+    let km = Quantity::unit(units::lookup("km").unwrap());
+    let h = Quantity::unit(units::lookup("h").unwrap());
+    let m = Quantity::unit(units::lookup("m").unwrap());
+    let s = Quantity::unit(units::lookup("s").unwrap());
+
+    let speed = km.div(&h);
+    assert_eq!(speed.to_string(), "1 km/h");
+    assert!((speed.convert(&m.div(&s)).unwrap().value - 1.0 / 3.6).abs() < 1e-15);
+    assert_eq!(m.div(&s.powi(2)).to_string(), "1 m/s^2");
+    assert_eq!(m.mul(&m).div(&km.mul(&s)).to_string(), "1 m^2/(km*s)");
+    assert_eq!(s.powi(-1).to_string(), "1 s^-1");
+    assert!(km.div(&m).is_dimensionless());
+    assert!(km.convert(&s).is_err());
+  }
+}
@@ -1,21 +1,86 @@
-use crate::ast::BinaryOperator;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::rc::Rc;
+use crate::ast::{BinaryOperator, UnaryOperator};
+use crate::bigint::BigInt;
 use crate::bytecode::Bytecode;
-use crate::object::Object;
+use crate::currency::{self, Money, Rates};
+use crate::decimal::{DecimalContext, RoundingMode};
+use crate::error::{Error, Result};
+use crate::native::{self, Arity, Context, Native};
+use crate::matrix;
+use crate::number;
+use crate::object::{Capture, Closure, Function, Object, MAX_LIST_DEPTH};
 use crate::opcode::Opcode;
+use crate::range::Range;
+use crate::span::Span;
+use crate::units;
+
+/// Default for [`VM::set_max_call_depth`].
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1000;
+
+/// How deeply natives such as `map` may call back into user-defined
+/// functions. Each level nests the dispatch loop on the Rust stack, so this
+/// is far below the call depth limit.
+pub const MAX_NESTED_CALLS: usize = 100;
+
+/// Default for [`VM::set_max_iterations`].
+pub const DEFAULT_MAX_ITERATIONS: usize = 10_000_000;
+
+/// A running call to a user-defined function, or the top-level program.
+#[derive(Debug)]
+struct Frame {
+  function: Rc<Function>,
+  /// Set when calling a closure, for the values it captured.
+  closure: Option<Rc<Closure>>,
+  /// Stack index of the first argument; the callee sits just below it.
+  slots: usize,
+  /// Where the caller resumes once this frame returns.
+  return_ip: usize,
+  call_span: Span,
+}
 
 #[derive(Debug)]
 pub struct VM {
   ip: usize,
   stack: Vec<Object>,
+  frames: Vec<Frame>,
+  max_call_depth: usize,
+  /// Calls from natives into user-defined functions that are still running.
+  nested_calls: usize,
+  /// Loop iterations run by the current program, against `max_iterations`.
+  iterations: usize,
+  max_iterations: usize,
+  globals: HashMap<String, Object>,
+  constants: HashMap<String, Object>,
+  natives: Vec<Native>,
+  native_names: HashMap<String, usize>,
+  decimal: Option<DecimalContext>,
+  rates: Option<Rc<Rates>>,
 }
 
 impl Default for VM {
   fn default() -> Self {
     let mut vm = Self {
       ip: 0,
-      stack: Vec::new()
+      stack: Vec::new(),
+      frames: Vec::new(),
+      max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+      nested_calls: 0,
+      iterations: 0,
+      max_iterations: DEFAULT_MAX_ITERATIONS,
+      globals: HashMap::new(),
+      constants: HashMap::new(),
+      natives: Vec::new(),
+      native_names: HashMap::new(),
+      decimal: None,
+      rates: None,
     };
 
+    for native in native::builtins() {
+      vm.define_native(native);
+    }
+
     vm.stack.reserve(256);
     vm
   }
@@ -26,92 +91,678 @@ impl VM {
     self.stack.pop().expect("stack underflow")
   }
 
-  fn binary_op(&mut self, left: Object, right: Object, op: BinaryOperator) -> Option<Object> {
-    match op {
-      BinaryOperator::Add => {
-        match (left, right) {
-          (Object::Number(left), Object::Number(right)) => Some(Object::Number(left + right)),
-          _ => None,
+  fn peek(&self) -> Object {
+    self.stack.last().expect("stack underflow").clone()
+  }
+
+  pub fn global(&self, name: &str) -> Option<Object> {
+    self.globals.get(name).cloned()
+  }
+
+  fn define_native(&mut self, native: Native) {
+    let index = self.natives.len();
+    self.native_names.insert(native.name.clone(), index);
+    self.natives.push(native);
+  }
+
+  /// Registers a host function callable from expressions as `name(...)`.
+  /// A later registration under the same name replaces the earlier one,
+  /// including built-ins.
+  pub fn register_fn<F>(&mut self, name: &str, arity: Arity, pure: bool, function: F)
+  where
+    F: Fn(&[Object]) -> std::result::Result<Object, String> + 'static,
+  {
+    self.define_native(Native::new(name, arity, pure, function));
+  }
+
+  /// Registers a read-only binding; assigning to `name` is a runtime error.
+  pub fn register_const(&mut self, name: &str, value: Object) {
+    self.globals.remove(name);
+    self.constants.insert(name.to_string(), value);
+  }
+
+  /// Enables decimal mode, in which fractional literals are read as
+  /// decimals, or disables it with `None`. Decimal results are always
+  /// rounded to the context's scale, using the default context when off.
+  pub fn set_decimal_mode(&mut self, context: Option<DecimalContext>) {
+    self.decimal = context;
+  }
+
+  pub fn decimal_mode(&self) -> Option<DecimalContext> {
+    self.decimal
+  }
+
+  /// Sets the exchange rates used by `to` and `in` conversions between
+  /// currencies, and makes `rates_timestamp()` report when they are from.
+  pub fn set_rates(&mut self, rates: Rates) {
+    let timestamp = rates.timestamp();
+
+    self.register_fn("rates_timestamp", Arity::Fixed(0), false, move |_| {
+      timestamp.map(Object::Integer).ok_or_else(|| "the loaded rates have no timestamp".to_string())
+    });
+
+    self.rates = Some(Rc::new(rates));
+  }
+
+  pub fn rates(&self) -> Option<&Rates> {
+    self.rates.as_deref()
+  }
+
+  fn convert_money(&self, value: &Object, to: &Money) -> std::result::Result<Object, String> {
+    let money = match value {
+      Object::Money(money) => money,
+      _ => return Err(format!("cannot convert {} to {}", value.type_name(), to.currency)),
+    };
+
+    if money.currency == to.currency {
+      return Ok(value.clone());
+    }
+
+    let rates = self.rates.as_ref().ok_or_else(|| "no exchange rates loaded".to_string())?;
+    let converted = rates.convert(money, to.currency, &self.decimal.unwrap_or_default())?;
+
+    Ok(Object::Money(Rc::new(converted)))
+  }
+
+  /// Limits how deeply user-defined functions may call each other, so that
+  /// runaway recursion is an error rather than exhausting memory.
+  pub fn set_max_call_depth(&mut self, depth: usize) {
+    self.max_call_depth = depth;
+  }
+
+  pub fn max_call_depth(&self) -> usize {
+    self.max_call_depth
+  }
+
+  /// Limits how many loop iterations one program may run in total, so that
+  /// a runaway loop is an error rather than never finishing.
+  pub fn set_max_iterations(&mut self, iterations: usize) {
+    self.max_iterations = iterations;
+  }
+
+  pub fn max_iterations(&self) -> usize {
+    self.max_iterations
+  }
+
+  pub fn native(&self, name: &str) -> Option<&Native> {
+    self.native_names.get(name).map(|index| &self.natives[*index])
+  }
+
+  fn lookup(&self, name: &str) -> Option<Object> {
+    if let Some(value) = self.constants.get(name) {
+      return Some(value.clone());
+    }
+
+    match self.globals.get(name) {
+      Some(value) => Some(value.clone()),
+      None => self.native_names.get(name).map(|index| Object::Native(*index)),
+    }
+  }
+
+  fn call(&mut self, count: usize, span: Span) -> Result<()> {
+    let args: Vec<Object> = self.stack.drain(self.stack.len() - count..).collect();
+    let callee = self.pop();
+
+    let result = self.call_value(&callee, &args).map_err(|message| Error::Runtime(message, span))?;
+    self.stack.push(result);
+
+    Ok(())
+  }
+
+  /// The function to run for a callee defined in the expression language.
+  fn user_function(callee: &Object) -> Option<(Rc<Function>, Option<Rc<Closure>>)> {
+    match callee {
+      Object::Function(function) => Some((function.clone(), None)),
+      Object::Closure(closure) => Some((closure.function.clone(), Some(closure.clone()))),
+      _ => None,
+    }
+  }
+
+  /// Pushes a frame for a call to `function` whose `count` arguments are on
+  /// top of the stack, above the function itself.
+  fn push_frame(&mut self, function: Rc<Function>, closure: Option<Rc<Closure>>, count: usize, span: Span) -> std::result::Result<(), String> {
+    let arity = Arity::Fixed(function.params.len());
+
+    if !arity.accepts(count) {
+      return Err(format!("{}() expects {}, got {}", function.name, arity, count));
+    }
+
+    // The bottom frame is the program itself rather than a call.
+    let depth = self.frames.len() - 1;
+
+    if depth >= self.max_call_depth {
+      return Err(format!("maximum call depth of {} exceeded", self.max_call_depth));
+    }
+
+    self.frames.push(Frame {
+      function,
+      closure,
+      slots: self.stack.len() - count,
+      return_ip: self.ip + 1,
+      call_span: span,
+    });
+
+    Ok(())
+  }
+
+  fn call_value(&mut self, callee: &Object, args: &[Object]) -> std::result::Result<Object, String> {
+    if let Some((function, closure)) = VM::user_function(callee) {
+      return self.call_function(callee, function, closure, args);
+    }
+
+    let native = match callee {
+      Object::Native(index) => self.natives[*index].clone(),
+      _ => return Err(format!("{} is not callable", callee.type_name())),
+    };
+
+    if !native.arity.accepts(args.len()) {
+      return Err(format!("{}() expects {}, got {}", native.name, native.arity, args.len()));
+    }
+
+    let result = (native.function)(self, args).map_err(|message| format!("{}(): {}", native.name, message))?;
+
+    if result.depth() > MAX_LIST_DEPTH {
+      return Err(format!("{}(): lists cannot nest more than {} deep", native.name, MAX_LIST_DEPTH));
+    }
+
+    Ok(result)
+  }
+
+  fn integer(value: Object, op: &dyn std::fmt::Display, span: Span) -> Result<i64> {
+    match value {
+      Object::Integer(n) => Ok(n),
+      Object::BigInt(_) => Err(Error::Runtime(format!("operands of '{}' must fit in 64 bits, got {}", op, value), span)),
+      Object::Number(n) if n.fract() == 0.0 && n >= i64::MIN as f64 && n < i64::MAX as f64 => Ok(n as i64),
+      _ => Err(Error::Runtime(format!("operands of '{}' must be integers, got {}", op, value), span)),
+    }
+  }
+
+  fn bitwise_op(&mut self, left: Object, right: Object, op: BinaryOperator, span: Span) -> Result<Object> {
+    let left = VM::integer(left, &op, span)?;
+    let right = VM::integer(right, &op, span)?;
+
+    let result = match op {
+      BinaryOperator::BitAnd => left & right,
+      BinaryOperator::BitOr => left | right,
+      BinaryOperator::BitXor => left ^ right,
+      BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight | BinaryOperator::ShiftRightUnsigned => {
+        if !(0..64).contains(&right) {
+          return Err(Error::Runtime(format!("shift amount {} is out of range 0..64", right), span));
         }
-      }
-      BinaryOperator::Subtract => {
-        match (left, right) {
-          (Object::Number(left), Object::Number(right)) => Some(Object::Number(left - right)),
-          _ => None,
+
+        match op {
+          BinaryOperator::ShiftLeft => left << right,
+          BinaryOperator::ShiftRight => left >> right,
+          _ => ((left as u64) >> right) as i64,
         }
       }
-      BinaryOperator::Multiply => {
-        match (left, right) {
-          (Object::Number(left), Object::Number(right)) => Some(Object::Number(left * right)),
-          _ => None,
-        }
+      _ => unreachable!(),
+    };
+
+    Ok(Object::Integer(result))
+  }
+
+  /// Applies an arithmetic operator to each pair of elements of two lists
+  /// of the same length, or between each element and a scalar.
+  fn elementwise_op(&mut self, left: Object, right: Object, op: BinaryOperator, span: Span) -> Result<Object> {
+    let pairs: Vec<(Object, Object)> = match (&left, &right) {
+      (Object::List(left), Object::List(right)) if left.len() != right.len() => {
+        return Err(Error::Runtime(format!("lists of different lengths for '{}': {} and {}", op, left.len(), right.len()), span));
       }
-      BinaryOperator::Divide => {
-        match (left, right) {
-          (Object::Number(left), Object::Number(right)) => Some(Object::Number(left / right)),
-          _ => None,
-        }
+      (Object::List(left), Object::List(right)) => left.iter().cloned().zip(right.iter().cloned()).collect(),
+      (Object::List(left), right) => left.iter().map(|left| (left.clone(), right.clone())).collect(),
+      (left, Object::List(right)) => right.iter().map(|right| (left.clone(), right.clone())).collect(),
+      _ => unreachable!(),
+    };
+
+    let items = pairs.into_iter()
+      .map(|(left, right)| self.binary_op(left, right, op.clone(), span))
+      .collect::<Result<Vec<_>>>()?;
+
+    Ok(Object::List(Rc::new(items)))
+  }
+
+  /// Resolves an index into a list or string of `len` elements, counting
+  /// from the end when negative.
+  fn position(index: &Object, len: usize, span: Span) -> Result<usize> {
+    let index = match index {
+      Object::Integer(n) => *n,
+      Object::BigInt(_) => return Err(Error::Runtime(format!("index {} is out of range for length {}", index, len), span)),
+      _ => return Err(Error::Runtime(format!("index must be an integer, got {}", index.type_name()), span)),
+    };
+
+    let position = if index < 0 { index.checked_add(len as i64) } else { Some(index) };
+
+    match position {
+      Some(position) if (0..len as i64).contains(&position) => Ok(position as usize),
+      _ => Err(Error::Runtime(format!("index {} is out of range for length {}", index, len), span)),
+    }
+  }
+
+  fn index(value: &Object, index: &Object, context: &DecimalContext, span: Span) -> Result<Object> {
+    match value {
+      Object::Range(r) => r.index(index, context).map_err(|message| Error::Runtime(message, span)),
+      Object::Matrix(m) => Ok(m.row(VM::position(index, m.rows(), span)?)),
+      Object::List(items) => Ok(items[VM::position(index, items.len(), span)?].clone()),
+      Object::String(s) => {
+        let position = VM::position(index, s.chars().count(), span)?;
+        Ok(Object::String(s.chars().nth(position).unwrap_or_default().to_string().into()))
       }
-      BinaryOperator::Modulo => {
-        match (left, right) {
-          (Object::Number(left), Object::Number(right)) => Some(Object::Number(left % right)),
-          _ => None,
+      _ => Err(Error::Runtime(format!("cannot index {}", value.type_name()), span)),
+    }
+  }
+
+  /// The element at `position` when looping over `value`, or `None` past
+  /// the end.
+  fn element(value: &Object, position: usize, context: &DecimalContext, span: Span) -> Result<Option<Object>> {
+    match value {
+      Object::Range(r) if r.count().is_none_or(|len| position < len) => {
+        r.get(&Object::Integer(position as i64), context).map(Some).map_err(|message| Error::Runtime(message, span))
+      }
+      Object::Range(_) => Ok(None),
+      Object::List(items) => Ok(items.get(position).cloned()),
+      Object::Matrix(m) => Ok((position < m.rows()).then(|| m.row(position))),
+      Object::String(s) => Ok(s.chars().nth(position).map(|ch| Object::String(ch.to_string().into()))),
+      _ => Err(Error::Runtime(format!("cannot iterate over {}", value.type_name()), span)),
+    }
+  }
+
+  /// Slices like Python: bounds count from the end when negative and are
+  /// clamped to the length, so out-of-range slices are just shorter. A slice
+  /// of a range is a list.
+  fn slice(value: &Object, start: Option<Object>, end: Option<Object>, context: &DecimalContext, span: Span) -> Result<Object> {
+    let len = match value {
+      Object::List(items) => BigInt::from_i64(items.len() as i64),
+      Object::Range(r) => r.len().clone(),
+      Object::Matrix(m) => BigInt::from_i64(m.rows() as i64),
+      Object::String(s) => BigInt::from_i64(s.chars().count() as i64),
+      _ => return Err(Error::Runtime(format!("cannot slice {}", value.type_name()), span)),
+    };
+
+    let bound = |bound: Option<Object>, default: &BigInt| match bound.as_ref().map(number::big) {
+      None => Ok(default.clone()),
+      Some(Some(n)) if n.is_negative() => Ok(n.add(&len).max(BigInt::zero())),
+      Some(Some(n)) => Ok(n.min(len.clone())),
+      Some(None) => Err(Error::Runtime(format!("slice bounds must be integers, got {}", bound.unwrap().type_name()), span)),
+    };
+
+    let start = bound(start, &BigInt::zero())?;
+    let end = bound(end, &len)?.max(start.clone());
+
+    if let Object::Range(r) = value {
+      let count = end.sub(&start).to_i64().filter(|&count| count as usize <= native::MAX_LIST_LENGTH);
+      let Some(count) = count else {
+        return Err(Error::Runtime(format!("slice would have more than {} elements", native::MAX_LIST_LENGTH), span));
+      };
+
+      let items = (0..count)
+        .map(|offset| r.get(&number::from_bigint(start.add(&BigInt::from_i64(offset))), context))
+        .collect::<std::result::Result<Vec<_>, _>>();
+      return items.map(|items| Object::List(Rc::new(items))).map_err(|message| Error::Runtime(message, span));
+    }
+
+    // Bounds within a list or string fit in a usize like its length does.
+    let start = start.to_i64().unwrap_or(0) as usize;
+    let end = end.to_i64().unwrap_or(0) as usize;
+
+    match value {
+      Object::List(items) => Ok(Object::List(Rc::new(items[start..end].to_vec()))),
+      Object::Matrix(m) => Ok(matrix::from_list(m.to_list()[start..end].to_vec())),
+      _ => Ok(Object::String(value.to_string().chars().skip(start).take(end - start).collect::<String>().into())),
+    }
+  }
+
+  fn binary_op(&mut self, left: Object, right: Object, op: BinaryOperator, span: Span) -> Result<Object> {
+    let result = match op {
+      BinaryOperator::BitAnd |
+      BinaryOperator::BitOr |
+      BinaryOperator::BitXor |
+      BinaryOperator::ShiftLeft |
+      BinaryOperator::ShiftRight |
+      BinaryOperator::ShiftRightUnsigned => {
+        return self.bitwise_op(left, right, op, span);
+      }
+      BinaryOperator::Add |
+      BinaryOperator::Subtract |
+      BinaryOperator::Multiply |
+      BinaryOperator::Divide |
+      BinaryOperator::Modulo |
+      BinaryOperator::Power if matches!(left, Object::Matrix(_)) || matches!(right, Object::Matrix(_)) => {
+        let result = matrix::arithmetic(&op, &left, &right, &self.decimal.unwrap_or_default());
+        return result.map_err(|message| Error::Runtime(message, span));
+      }
+      BinaryOperator::Add |
+      BinaryOperator::Subtract |
+      BinaryOperator::Multiply |
+      BinaryOperator::Divide |
+      BinaryOperator::Modulo |
+      BinaryOperator::Power if matches!(left, Object::List(_)) || matches!(right, Object::List(_)) => {
+        return self.elementwise_op(left, right, op, span);
+      }
+      BinaryOperator::Add if matches!((&left, &right), (Object::String(_), Object::String(_))) => {
+        Some(Object::String(Rc::from(format!("{}{}", left, right))))
+      }
+      BinaryOperator::Add |
+      BinaryOperator::Subtract |
+      BinaryOperator::Multiply |
+      BinaryOperator::Divide |
+      BinaryOperator::Modulo |
+      BinaryOperator::Power => {
+        if matches!(left, Object::Money(_)) || matches!(right, Object::Money(_)) {
+          let result = currency::arithmetic(&op, &left, &right, &self.decimal.unwrap_or_default());
+          return result.map_err(|message| Error::Runtime(message, span));
+        }
+
+        if matches!(left, Object::Quantity(_)) || matches!(right, Object::Quantity(_)) {
+          let result = units::arithmetic(&op, &left, &right);
+          return result.map_err(|message| Error::Runtime(message, span));
+        }
+
+        if left.is_number() && right.is_number() {
+          let result = number::arithmetic(&op, &left, &right, &self.decimal.unwrap_or_default());
+          return result.map_err(|message| Error::Runtime(message, span));
         }
+
+        None
       }
       BinaryOperator::Equal => {
-        match (left, right) {
-          (Object::Number(left), Object::Number(right)) => Some(Object::Boolean(left == right)),
+        match (&left, &right) {
+          (left, right) if left.is_number() && right.is_number() => {
+            Some(Object::Boolean(number::equal(left, right)))
+          }
+          (Object::Quantity(_), _) | (_, Object::Quantity(_)) => {
+            let ordering = units::compare(&left, &right).unwrap_or(None);
+            Some(Object::Boolean(ordering == Some(Ordering::Equal)))
+          }
+          (Object::Money(_), _) | (_, Object::Money(_)) => {
+            let ordering = currency::compare(&left, &right).unwrap_or(None);
+            Some(Object::Boolean(ordering == Some(Ordering::Equal)))
+          }
           (Object::Boolean(left), Object::Boolean(right)) => Some(Object::Boolean(left == right)),
+          (Object::String(left), Object::String(right)) => Some(Object::Boolean(left == right)),
+          (Object::Matrix(left), Object::Matrix(right)) => {
+            let equal = (left.rows(), left.cols()) == (right.rows(), right.cols())
+              && (0..left.rows()).all(|row| (0..left.cols()).all(|col| number::equal(left.get(row, col), right.get(row, col))));
+
+            Some(Object::Boolean(equal))
+          }
+          (Object::List(left), Object::List(right)) => {
+            let equal = left.len() == right.len() && left.iter().zip(right.iter()).all(|(left, right)| {
+              let result = self.binary_op(left.clone(), right.clone(), BinaryOperator::Equal, span);
+              result.is_ok_and(|equal| Object::is_truthy(&equal))
+            });
+
+            Some(Object::Boolean(equal))
+          }
+          (Object::Range(left), Object::Range(right)) => Some(Object::Boolean(left == right)),
+          (Object::Native(left), Object::Native(right)) => Some(Object::Boolean(left == right)),
+          (Object::Function(left), Object::Function(right)) => Some(Object::Boolean(Rc::ptr_eq(left, right))),
+          (Object::Closure(left), Object::Closure(right)) => Some(Object::Boolean(Rc::ptr_eq(left, right))),
           _ => None,
         }
       }
       BinaryOperator::GreaterThan => {
-        match (left, right) {
-          (Object::Number(left), Object::Number(right)) => Some(Object::Boolean(left > right)),
+        match (&left, &right) {
+          (left, right) if left.is_real() && right.is_real() => {
+            Some(Object::Boolean(number::compare(left, right) == Some(Ordering::Greater)))
+          }
+          (Object::Quantity(_), _) | (_, Object::Quantity(_)) => {
+            let ordering = units::compare(&left, &right).map_err(|message| Error::Runtime(message, span))?;
+            Some(Object::Boolean(ordering == Some(Ordering::Greater)))
+          }
+          (Object::Money(_), _) | (_, Object::Money(_)) => {
+            let ordering = currency::compare(&left, &right).map_err(|message| Error::Runtime(message, span))?;
+            Some(Object::Boolean(ordering == Some(Ordering::Greater)))
+          }
+          (Object::String(left), Object::String(right)) => Some(Object::Boolean(left.cmp(right) == Ordering::Greater)),
           _ => None,
         }
       }
       BinaryOperator::LessThan => {
-        match (left, right) {
-          (Object::Number(left), Object::Number(right)) => Some(Object::Boolean(left < right)),
+        match (&left, &right) {
+          (left, right) if left.is_real() && right.is_real() => {
+            Some(Object::Boolean(number::compare(left, right) == Some(Ordering::Less)))
+          }
+          (Object::Quantity(_), _) | (_, Object::Quantity(_)) => {
+            let ordering = units::compare(&left, &right).map_err(|message| Error::Runtime(message, span))?;
+            Some(Object::Boolean(ordering == Some(Ordering::Less)))
+          }
+          (Object::Money(_), _) | (_, Object::Money(_)) => {
+            let ordering = currency::compare(&left, &right).map_err(|message| Error::Runtime(message, span))?;
+            Some(Object::Boolean(ordering == Some(Ordering::Less)))
+          }
+          (Object::String(left), Object::String(right)) => Some(Object::Boolean(left.cmp(right) == Ordering::Less)),
           _ => None,
         }
       }
       _ => None,
+    };
+
+    result.ok_or_else(|| {
+      Error::Runtime(format!("invalid operands for '{}': {} and {}", op, left.type_name(), right.type_name()), span)
+    })
+  }
+
+  /// Calls a user-defined function from outside the dispatch loop, as a
+  /// native such as `map` does, running it to completion.
+  fn call_function(
+    &mut self,
+    callee: &Object,
+    function: Rc<Function>,
+    closure: Option<Rc<Closure>>,
+    args: &[Object],
+  ) -> std::result::Result<Object, String> {
+    if self.nested_calls >= MAX_NESTED_CALLS {
+      return Err(format!("maximum nesting of {} calls from natives exceeded", MAX_NESTED_CALLS));
     }
+
+    let (floor, height, ip) = (self.frames.len(), self.stack.len(), self.ip);
+
+    self.stack.push(callee.clone());
+    self.stack.extend(args.iter().cloned());
+    self.nested_calls += 1;
+
+    let result = match self.push_frame(function.clone(), closure, args.len(), Span::default()) {
+      Ok(()) => self.execute(floor).map_err(|err| format!("{}(): {}", function.name, err.message())),
+      Err(message) => Err(message),
+    };
+
+    self.nested_calls -= 1;
+    self.frames.truncate(floor);
+    self.stack.truncate(height);
+    self.ip = ip;
+
+    result
   }
 
-  pub fn run(&mut self, bytecode: Bytecode) -> Option<Object> {
+  pub fn run(&mut self, bytecode: Bytecode) -> Result<Object> {
+    let script = Function {
+      name: "<script>".to_string(),
+      params: Vec::new(),
+      chunk: bytecode,
+      captures: Vec::new(),
+    };
+
+    self.stack.clear();
+    self.frames.clear();
+    self.nested_calls = 0;
+    self.iterations = 0;
+    self.frames.push(Frame {
+      function: Rc::new(script),
+      closure: None,
+      slots: 0,
+      return_ip: 0,
+      call_span: Span::default(),
+    });
+
+    self.execute(0)
+  }
+
+  /// Runs the frame at index `floor` until it returns. An error inside a
+  /// function it called is reported at the outermost such call, prefixed
+  /// with the name of each function on the way in.
+  fn execute(&mut self, floor: usize) -> Result<Object> {
+    self.ip = 0;
+
+    let result = self.dispatch(floor).map_err(|err| {
+      if self.frames.len() <= floor + 1 {
+        return err;
+      }
+
+      let frames = self.frames.split_off(floor + 1);
+      let names: Vec<String> = frames.iter().map(|frame| format!("{}(): ", frame.function.name)).collect();
+
+      Error::Runtime(format!("{}{}", names.concat(), err.message()), frames[0].call_span)
+    });
+
+    // Nested runs from natives each add their own names, so the whole chain
+    // is only known once the error reaches the program.
+    match result {
+      Err(Error::Runtime(message, span)) if floor == 0 => Err(Error::Runtime(collapse_names(&message), span)),
+      result => result,
+    }
+  }
+
+  fn dispatch(&mut self, floor: usize) -> Result<Object> {
+    let mut function = self.frames[floor].function.clone();
+    let mut closure = self.frames[floor].closure.clone();
+    let mut slots = self.frames[floor].slots;
+
     loop {
+      let bytecode = &function.chunk;
       let opcode = bytecode.codes[self.ip];
+      let span = bytecode.spans[self.ip];
 
       match opcode {
         Opcode::Constant(constant) => {
-          let constant = bytecode.constants[constant];
+          let constant = bytecode.constants[constant].clone();
           self.stack.push(constant);
         }
+        Opcode::GetLocal(slot) => {
+          let value = self.stack[slots + slot].clone();
+          self.stack.push(value);
+        }
+        Opcode::SetLocal(slot) => {
+          self.stack[slots + slot] = self.peek();
+        }
+        Opcode::GetUpvalue(index) => {
+          let value = closure.as_ref().expect("no closure").upvalues[index].clone();
+          self.stack.push(value);
+        }
+        Opcode::Closure(constant) => {
+          let Object::Function(prototype) = &bytecode.constants[constant] else {
+            unreachable!("closure of a non-function constant");
+          };
+
+          let upvalues = prototype.captures.iter().map(|capture| match *capture {
+            Capture::Local(slot) => self.stack[slots + slot].clone(),
+            Capture::Upvalue(index) => closure.as_ref().expect("no closure").upvalues[index].clone(),
+          }).collect();
+
+          let value = Object::Closure(Rc::new(Closure {
+            function: prototype.clone(),
+            upvalues,
+          }));
+
+          self.stack.push(value);
+        }
+        Opcode::GetGlobal(name) => {
+          let name = &bytecode.names[name];
+
+          match self.lookup(name) {
+            Some(value) => self.stack.push(value),
+            None => {
+              return Err(Error::Runtime(format!("'{}' is not defined", name), span));
+            }
+          }
+        }
+        Opcode::SetGlobal(name) => {
+          let name = &bytecode.names[name];
+
+          if self.constants.contains_key(name) {
+            return Err(Error::Runtime(format!("cannot assign to constant '{}'", name), span));
+          }
+
+          let value = self.peek();
+          self.globals.insert(name.clone(), value);
+        }
+        Opcode::Negate => {
+          let value = self.pop();
+
+          match number::negate(&value) {
+            Some(result) => self.stack.push(result),
+            None => {
+              return Err(Error::Runtime(format!("invalid operand for '-': {}", value.type_name()), span));
+            }
+          }
+        }
+        Opcode::BitNot => {
+          let value = VM::integer(self.pop(), &UnaryOperator::BitNot, span)?;
+          self.stack.push(Object::Integer(!value));
+        }
         Opcode::Not => {
           let value = self.pop();
           self.stack.push(Object::Boolean(Object::is_falsey(&value)));
         }
-        Opcode::And => {
-          let right = self.pop();
-          let left = self.pop();
+        Opcode::Pop => {
+          self.pop();
+        }
+        Opcode::PopBelow(count) => {
+          let value = self.pop();
 
-          if Object::is_truthy(&left) && Object::is_truthy(&right) {
-            self.stack.push(Object::Boolean(true));
-          } else {
-            self.stack.push(Object::Boolean(false));
+          self.stack.truncate(self.stack.len() - count);
+          self.stack.push(value);
+        }
+        Opcode::Jump(target) => {
+          self.ip = target;
+          continue;
+        }
+        Opcode::JumpIfFalse(target) => {
+          if Object::is_falsey(&self.peek()) {
+            self.ip = target;
+            continue;
           }
         }
-        Opcode::Or => {
-          let right = self.pop();
-          let left = self.pop();
+        Opcode::JumpIfTrue(target) => {
+          if Object::is_truthy(&self.peek()) {
+            self.ip = target;
+            continue;
+          }
+        }
+        Opcode::Loop(target) => {
+          self.iterations += 1;
 
-          if Object::is_truthy(&left) || Object::is_truthy(&right) {
-            self.stack.push(Object::Boolean(true));
-          } else {
-            self.stack.push(Object::Boolean(false));
+          if self.iterations > self.max_iterations {
+            return Err(Error::Runtime(format!("loop iteration limit of {} exceeded", self.max_iterations), span));
+          }
+
+          self.ip = target;
+          continue;
+        }
+        Opcode::IterNext(slot, target) => {
+          let Object::Integer(position) = self.stack[slots + slot + 1] else {
+            unreachable!("loop position is not an integer");
+          };
+
+          match VM::element(&self.stack[slots + slot], position as usize, &self.decimal_context(), span)? {
+            Some(element) => {
+              self.stack[slots + slot + 1] = Object::Integer(position + 1);
+              self.stack.push(element);
+            }
+            None => {
+              self.ip = target;
+              continue;
+            }
+          }
+        }
+        Opcode::Range(inclusive, has_step) => {
+          let step = if has_step { self.pop() } else { Object::Integer(1) };
+          let end = self.pop();
+          let start = self.pop();
+
+          match Range::new(start, end, step, inclusive, &self.decimal_context()) {
+            Ok(range) => self.stack.push(Object::Range(Rc::new(range))),
+            Err(message) => return Err(Error::Runtime(message, span)),
           }
         }
         Opcode::Add |
@@ -119,6 +770,13 @@ impl VM {
         Opcode::Multiply|
         Opcode::Divide |
         Opcode::Modulo |
+        Opcode::Power |
+        Opcode::BitAnd |
+        Opcode::BitOr |
+        Opcode::BitXor |
+        Opcode::ShiftLeft |
+        Opcode::ShiftRight |
+        Opcode::ShiftRightUnsigned |
         Opcode::Equal |
         Opcode::Greater |
         Opcode::Less => {
@@ -130,43 +788,187 @@ impl VM {
             Opcode::Multiply => BinaryOperator::Multiply,
             Opcode::Divide => BinaryOperator::Divide,
             Opcode::Modulo => BinaryOperator::Modulo,
+            Opcode::Power => BinaryOperator::Power,
+            Opcode::BitAnd => BinaryOperator::BitAnd,
+            Opcode::BitOr => BinaryOperator::BitOr,
+            Opcode::BitXor => BinaryOperator::BitXor,
+            Opcode::ShiftLeft => BinaryOperator::ShiftLeft,
+            Opcode::ShiftRight => BinaryOperator::ShiftRight,
+            Opcode::ShiftRightUnsigned => BinaryOperator::ShiftRightUnsigned,
             Opcode::Equal => BinaryOperator::Equal,
             Opcode::Greater => BinaryOperator::GreaterThan,
             Opcode::Less => BinaryOperator::LessThan,
             _ => unreachable!()
           };
 
-          let result = match self.binary_op(left, right, op) {
-            Some(result) => result,
-            None => {
-              println!("Invalid operation");
-              break;
-            }
-          };
+          let result = self.binary_op(left, right, op, span)?;
 
           self.stack.push(result);
         }
         Opcode::Return => {
-          return Some(self.pop())
+          let result = self.stack.pop().ok_or_else(|| Error::Runtime("program produced no value".to_string(), span))?;
+          let frame = self.frames.pop().expect("no call frame");
+
+          self.stack.truncate(frame.slots.saturating_sub(1));
+
+          if self.frames.len() == floor {
+            return Ok(result);
+          }
+
+          let caller = self.frames.last().expect("no call frame");
+
+          self.stack.push(result);
+          self.ip = frame.return_ip;
+          function = caller.function.clone();
+          closure = caller.closure.clone();
+          slots = caller.slots;
+          continue;
+        }
+        Opcode::Convert(constant) => {
+          let value = self.pop();
+
+          let result = match &bytecode.constants[constant] {
+            Object::Quantity(unit) => units::convert(&value, unit),
+            Object::Money(money) => self.convert_money(&value, money),
+            _ => unreachable!("conversion target is not a unit or currency"),
+          };
+
+          match result {
+            Ok(result) => self.stack.push(result),
+            Err(message) => return Err(Error::Runtime(message, span)),
+          }
+        }
+        Opcode::BuildList(count) => {
+          let items = self.stack.split_off(self.stack.len() - count);
+
+          if items.iter().any(|item| item.depth() >= MAX_LIST_DEPTH) {
+            return Err(Error::Runtime(format!("lists cannot nest more than {} deep", MAX_LIST_DEPTH), span));
+          }
+
+          self.stack.push(matrix::from_list(items));
+        }
+        Opcode::Index => {
+          let index = self.pop();
+          let value = self.pop();
+
+          self.stack.push(VM::index(&value, &index, &self.decimal_context(), span)?);
+        }
+        Opcode::Slice(has_start, has_end) => {
+          let end = if has_end { Some(self.pop()) } else { None };
+          let start = if has_start { Some(self.pop()) } else { None };
+          let value = self.pop();
+
+          self.stack.push(VM::slice(&value, start, end, &self.decimal_context(), span)?);
+        }
+        Opcode::Call(count) => {
+          let count = count as usize;
+
+          if let Some((callee, captured)) = VM::user_function(&self.stack[self.stack.len() - count - 1]) {
+            self.push_frame(callee.clone(), captured.clone(), count, span).map_err(|message| Error::Runtime(message, span))?;
+            self.ip = 0;
+            function = callee;
+            closure = captured;
+            slots = self.stack.len() - count;
+            continue;
+          }
+
+          self.call(count, span)?;
         }
-        _ => {}
       }
 
       self.ip += 1;
     }
+  }
+}
 
-    None
+impl Context for VM {
+  fn call(&mut self, callee: &Object, args: &[Object]) -> std::result::Result<Object, String> {
+    self.call_value(callee, args)
   }
+
+  fn binary_op(&mut self, op: BinaryOperator, left: Object, right: Object) -> std::result::Result<Object, String> {
+    VM::binary_op(self, left, right, op, Span::default()).map_err(|err| err.message().to_string())
+  }
+
+  fn decimal_context(&self) -> DecimalContext {
+    self.decimal.unwrap_or_default()
+  }
+}
+
+/// Shortens the `name(): ` prefixes of an error message, where recursion
+/// would otherwise repeat them once per level: a repeated name is kept once
+/// and a repeated cycle is written as `(f(): g()) ×n: `.
+fn collapse_names(message: &str) -> String {
+  let mut names = Vec::new();
+  let mut rest = message;
+
+  while let Some(index) = rest.find("(): ") {
+    let name = &rest[..index];
+
+    if name.is_empty() || !name.chars().all(|ch| ch.is_alphanumeric() || matches!(ch, '_' | '<' | '>')) {
+      break;
+    }
+
+    names.push(name);
+    rest = &rest[index + 4..];
+  }
+
+  let mut result = String::new();
+  let mut i = 0;
+
+  while i < names.len() {
+    let cycle = (1..=8.min((names.len() - i) / 2)).find_map(|len| {
+      let count = names[i..].chunks_exact(len).take_while(|chunk| *chunk == &names[i..i + len]).count();
+      (count > 1).then_some((len, count))
+    });
+
+    match cycle {
+      Some((1, count)) => {
+        result.push_str(&format!("{}(): ", names[i]));
+        i += count;
+      },
+      Some((len, count)) => {
+        let cycle: Vec<String> = names[i..i + len].iter().map(|name| format!("{}()", name)).collect();
+        result.push_str(&format!("({}) ×{}: ", cycle.join(": "), count));
+        i += len * count;
+      },
+      None => {
+        result.push_str(&format!("{}(): ", names[i]));
+        i += 1;
+      },
+    }
+  }
+
+  result + rest
 }
 
 #[cfg(test)]
 mod tests {
+  use std::rc::Rc;
+  use crate::bigint::BigInt;
   use crate::compiler::Compiler;
+  use crate::error::Error;
   use crate::lexer::Lexer;
   use crate::object::Object;
   use crate::parser::Parser;
+  use crate::rational::Rational;
   use crate::vm::VM;
 
+  /// Runs `input` as a whole program; lexing, parsing and compiling must
+  /// succeed.
+  fn run(vm: &mut VM, input: &str) -> Result<Object, Error> {
+    let mut lexer = Lexer::new(input);
+    let mut parser = Parser::new(lexer.lex().unwrap());
+    let bytecode = Compiler::new().compile(&parser.parse().unwrap()).unwrap();
+
+    vm.run(bytecode)
+  }
+
+  /// Like `run`, but with the result displayed and an error as its message.
+  fn eval(vm: &mut VM, input: &str) -> Result<String, String> {
+    run(vm, input).map(|result| result.to_string()).map_err(|err| err.message().to_string())
+  }
+
   #[test]
   fn execute() {
     let testcases = vec![
@@ -261,19 +1063,677 @@ mod tests {
       (
         "10 > 1",
         Object::Boolean(true)
+      ),
+      (
+        "-5 + 2",
+        Object::Integer(-3)
+      ),
+      (
+        "--4 * -2",
+        Object::Integer(-8)
+      ),
+      (
+        "!true",
+        Object::Boolean(false)
+      ),
+      (
+        "!(1 > 2) && !false",
+        Object::Boolean(true)
+      ),
+      (
+        "2^10",
+        Object::Integer(1024)
+      ),
+      (
+        "-2^2",
+        Object::Integer(-4)
+      ),
+      (
+        "2 ** 3 ** 2",
+        Object::Integer(512)
+      ),
+      (
+        "2^-1 * 3",
+        Object::Rational(Rational::new(3, 2).unwrap())
+      ),
+      (
+        "1 > 0 ? 10 : 20",
+        Object::Integer(10)
+      ),
+      (
+        "-4 > 0 ? sqrt(-4) : 0",
+        Object::Integer(0)
+      ),
+      (
+        "0 ? 1 : 0 ? 2 : 3",
+        Object::Integer(3)
+      ),
+      (
+        "1 + (if false then nope() else 2) * 3",
+        Object::Integer(7)
+      ),
+      (
+        "6 & 3",
+        Object::Integer(2)
+      ),
+      (
+        "6 | 3 xor 1",
+        Object::Integer(6)
+      ),
+      (
+        "~5",
+        Object::Integer(-6)
+      ),
+      (
+        "1 << 4 | 1",
+        Object::Integer(17)
+      ),
+      (
+        "-16 >> 2",
+        Object::Integer(-4)
+      ),
+      (
+        "-1 >>> 60",
+        Object::Integer(15)
+      ),
+      (
+        "0xFF + 0b1010 + 0o755 + 1_000",
+        Object::Integer(1758)
+      ),
+      (
+        "1e-9 * 2.5E+3 * 1e6",
+        Object::Rational(Rational::new(5, 2).unwrap())
+      ),
+      (
+        "0xF0 >> 4",
+        Object::Integer(15)
+      ),
+      (
+        "0.1 + 0.2 == 0.3",
+        Object::Boolean(true)
+      ),
+      (
+        "1/3*3",
+        Object::Integer(1)
+      ),
+      (
+        "7 / 2 - 1/6",
+        Object::Rational(Rational::new(10, 3).unwrap())
+      ),
+      (
+        "9223372036854775807 + 1",
+        Object::BigInt(Rc::new(BigInt::parse("9223372036854775808", 10).unwrap()))
+      ),
+      (
+        "1/3 > 0.333",
+        Object::Boolean(true)
+      ),
+      (
+        "1 && 2",
+        Object::Integer(2)
+      ),
+      (
+        "0 || 3",
+        Object::Integer(3)
+      ),
+      (
+        "0 && 3 || false",
+        Object::Boolean(false)
+      ),
+      (
+        "false && nope()",
+        Object::Boolean(false)
+      ),
+      (
+        "true || nope()",
+        Object::Boolean(true)
       )
     ];
 
     for (input, expected) in testcases {
       let mut lexer = Lexer::new(input);
-      let mut parser = Parser::new(lexer.lex());
+      let mut parser = Parser::new(lexer.lex().unwrap());
       let mut compiler = Compiler::new();
-      let mut bytecode = compiler.compile(&parser.parse());
+      let bytecode = compiler.compile(&parser.parse().unwrap()).unwrap();
       println!("bytecode: {:?}", bytecode);
       let mut vm = VM::default();
       let result = vm.run(bytecode).unwrap();
 
-      assert_eq!(result, expected);
+      // Decimal literals evaluate exactly, so compare against the float
+      // reference value within rounding error.
+      match expected {
+        Object::Number(expected) => {
+          let result = result.to_f64().unwrap();
+          assert!((result - expected).abs() <= expected.abs() * 1e-12, "{}: {} != {}", input, result, expected);
+        }
+        _ => assert_eq!(result, expected, "{}", input),
+      }
+    }
+  }
+
+  #[test]
+  fn globals() {
+    let testcases = vec![
+      ("rate = 0.07", Object::Rational(Rational::new(7, 100).unwrap())),
+      ("price = 100", Object::Integer(100)),
+      ("price * (1 + rate)", Object::Integer(107)),
+      ("rate = rate * 2", Object::Rational(Rational::new(7, 50).unwrap())),
+      ("price * rate", Object::Integer(14)),
+    ];
+
+    let mut vm = VM::default();
+
+    for (input, expected) in testcases {
+      assert_eq!(run(&mut vm, input).unwrap(), expected);
+    }
+
+    assert!(eval(&mut vm, "missing + 1").is_err());
+  }
+
+  #[test]
+  fn natives() {
+    let testcases = vec![
+      ("sqrt(16) + abs(-2)", Object::Number(6.0)),
+      ("log(8, 2)", Object::Number(3.0)),
+      ("max(3, 9, 4) - min(7, 2)", Object::Integer(7)),
+      ("clamp(15, 0, 10)", Object::Integer(10)),
+      ("clamp(1/3, 0, 1)", Object::Rational(Rational::new(1, 3).unwrap())),
+      ("hypot(3, 4)", Object::Number(5.0)),
+      ("sign(-3) + sign(0)", Object::Integer(-1)),
+      ("round(2.5) + floor(-1.5) + ceil(1.2) + trunc(-1.7)", Object::Integer(2)),
+      ("round(2.5 * 1.0 ^ 0.5)", Object::Number(3.0)),
+      ("float(1/4)", Object::Number(0.25)),
+      ("round(0.125, 2) + round(1.5, 0, \"half_even\")", Object::Rational(Rational::new(213, 100).unwrap())),
+      ("round(-1234, -2, \"ceiling\")", Object::Integer(-1200)),
+      ("round(2.5 * 1.0 ^ 0.5, 0, \"down\")", Object::Number(2.0)),
+      ("atan2(1, 1) * 4", Object::Number(std::f64::consts::PI)),
+      ("sin(0) + cos(0) + ln(1)", Object::Number(1.0)),
+    ];
+
+    for (input, expected) in testcases {
+      assert_eq!(run(&mut VM::default(), input).unwrap(), expected, "{}", input);
+    }
+
+    let errors = vec![
+      "sin(1, 2)",
+      "max()",
+      "nope(1)",
+      "sqrt(true)",
+      "clamp(1, 5, 0)",
+      "(1 + 2)(3)",
+      "1.5 & 1",
+      "1 << 64",
+      "~true",
+      "1 / 0",
+      "1 % 0",
+      "0 ^ -1",
+      "(0 / 5) ^ -2",
+      "2 ^ 100000000",
+      "3 ^ (2^64)",
+      "round(1.5, 0, 0)",
+      "round(1.5, 0, \"sideways\")",
+      "round(1.5, 0, HALF_UP)",
+    ];
+
+    for input in errors {
+      assert!(eval(&mut VM::default(), input).is_err(), "{}", input);
+    }
+  }
+
+  #[test]
+  fn bigint() {
+    let testcases = vec![
+      ("2^200", "1606938044258990275541962092341162602522202993782792835301376"),
+      ("factorial(50)", "30414093201713378043612608166064768844377641568960512000000000000"),
+      ("factorial(25) / factorial(23)", "600"),
+      ("comb(100, 50)", "100891344545564193334812497256"),
+      ("gcd(2^100, 6^50)", "1125899906842624"),
+      ("-9223372036854775807 - 1 - 1", "-9223372036854775809"),
+      ("(-9223372036854775807 - 1) / -1", "9223372036854775808"),
+      ("(-9223372036854775807 - 1) % -1", "0"),
+      ("1 ^ (2^64) + (-1) ^ (2^64 + 1) + 0 ^ (2^64)", "0"),
+      ("0 ^ 0 + 0 ^ 2", "1"),
+      ("2 ^ -2", "0.25"),
+      ("(2^64 + 1) % 10", "7"),
+      ("2^70 / 2^72", "0.25"),
+      ("0xFFFF_FFFF_FFFF_FFFF_FF", "4722366482869645213695"),
+      ("abs(-2^80) == 2^80", "true"),
+      ("2^64 > 1/3 && 1/3 < 2^64", "true"),
+      ("2^64 * 0.5", "9223372036854775808"),
+      ("2^64 * (3/4)", "13835058055282163712"),
+      ("0.5 * 2^65 - 2^64", "0"),
+      ("2^64 / (1/2)", "36893488147419103232"),
+      ("2^64 % (3/2)", "1"),
+      ("2^64 / (2^64 * 3)", "1/3"),
+      ("2^64 * 0.5 == 2^63", "true"),
+      ("1e30 + 1", "1000000000000000000000000000001"),
+      ("1.5e20", "150000000000000000000"),
+      ("12345678901234567890.50e1", "123456789012345678905"),
+      ("len(str(1e999))", "1000"),
+    ];
+
+    for (input, expected) in testcases {
+      assert_eq!(eval(&mut VM::default(), input).unwrap(), expected, "{}", input);
+    }
+
+    // Products past the size limit fail before doing any of the work.
+    assert_eq!(eval(&mut VM::default(), "x = 2^524288; x * x").unwrap_err(), "integer too large");
+
+    // Fractions too large for 64 bits are an error rather than rounded.
+    for input in ["(2^64) / 3", "2^64 + 1/2 - 2^64", "1/3 + 2^70"] {
+      assert!(eval(&mut VM::default(), input).unwrap_err().contains("too large for a fraction"), "{}", input);
+    }
+  }
+
+  #[test]
+  fn strings() {
+    let testcases = vec![
+      (r#""tax" + ": " + str(1/4)"#, "tax: 0.25"),
+      (r#""a\tb" == "a\u{9}b""#, "true"),
+      (r#""apple" < "banana""#, "true"),
+      (r#"len("héllo") + len("")"#, "5"),
+      (r#"upper("abc") + lower("DEF")"#, "ABCdef"),
+      (r#"substr("calculator", 4) + substr("calculator", 0, 4)"#, "ulatorcalc"),
+      (r#"substr("abc", 1, 99)"#, "bc"),
+      (r#"format("{} costs {:.2} ({{net}})", "tea", 3)"#, "tea costs 3.00 ({net})"),
+      (r#"format("{:.1}%", 2/3 * 100)"#, "66.7%"),
+      (r#"num("-1.5") * 2"#, "-3"),
+      (r#"num(" 12345678901234567890 ") + 1"#, "12345678901234567891"),
+      (r#"num("1e-3")"#, "0.001"),
+      (r#"if "" then 1 else 2"#, "2"),
+    ];
+
+    for (input, expected) in testcases {
+      assert_eq!(eval(&mut VM::default(), input).unwrap(), expected, "{}", input);
+    }
+
+    let invalid = [
+      r#""a" + 1"#, r#""a" < 1"#, r#"-"a""#, r#"len(1)"#, r#"substr("abc", 4)"#, r#"substr("abc", -1)"#,
+      r#"format("{}")"#, r#"format("{}", 1, 2)"#, r#"format("{:x}", 1)"#, r#"format("{", 1)"#, r#"num("abc")"#,
+    ];
+
+    for input in invalid {
+      assert!(eval(&mut VM::default(), input).is_err(), "{}", input);
+    }
+  }
+
+  #[test]
+  fn lists() {
+    let testcases = vec![
+      ("[1, 2, 3] + [10, 20, 30]", "[11, 22, 33]"),
+      ("[1, 2, 3] * 2 - 1", "[1, 3, 5]"),
+      ("1 / [2, 4]", "[0.5, 0.25]"),
+      ("-[1, [2, 3]]", "[-1, [-2, -3]]"),
+      ("[5 USD, 2 USD] * 2", "[10 USD, 4 USD]"),
+      ("[10, 20, 30][0] + [10, 20, 30][-1]", "40"),
+      ("[1, 2, 3, 4][1:3]", "[2, 3]"),
+      ("[1, 2, 3, 4][-2:] + [1, 2, 3, 4][:2]", "[4, 6]"),
+      ("[1, 2][5:]", "[]"),
+      (r#""hello"[1:-1] + "hello"[0]"#, "ellh"),
+      ("[1, 2] == [1, 2] && [1, 2] != [2, 1] && [1] != [1, 2]", "true"),
+      (r#"[1, "a"] == [1, 2]"#, "false"),
+      (r#"["a, b", true]"#, r#"["a, b", true]"#),
+      ("sum([1, 2, 3.5]) + sum([])", "6.5"),
+      ("mean([1, 2, 4])", "7/3"),
+      ("sum([1 km, 500 m])", "1.5 km"),
+      ("len([1, [2, 3]])", "2"),
+      ("sort([3, -1, 2.5, 1/2])", "[-1, 0.5, 2.5, 3]"),
+      (r#"sort(["pear", "apple"])"#, r#"["apple", "pear"]"#),
+      ("sort([2 km, 300 m])", "[300 m, 2 km]"),
+      ("map(sqrt, [4, 9])", "[2, 3]"),
+      ("filter(abs, [0, 1, -2, 0])", "[1, -2]"),
+      ("reduce(max, [3, 9, 2]) + reduce(min, [], 7)", "16"),
+      ("range(4)", "0..4"),
+      ("range(4)[:]", "[0, 1, 2, 3]"),
+      ("range(1, 2, 1/4)[:]", "[1, 1.25, 1.5, 1.75]"),
+      ("range(3, 0, -1)[:]", "[3, 2, 1]"),
+      ("range(2, 5) == (2..5)", "true"),
+      ("len(range(0, 1e9))", "1000000000"),
+      ("if [] then 1 else 2", "2"),
+    ];
+
+    for (input, expected) in testcases {
+      assert_eq!(eval(&mut VM::default(), input).unwrap(), expected, "{}", input);
+    }
+
+    let invalid = [
+      "[1, 2] + [1, 2, 3]", "[1, 2][2]", "[1, 2][-3]", "[1][0.5]", "5[0]", "[1, 2] < [3, 4]", "mean([])",
+      "sort([1, true])", "sort([1 USD, 1 EUR])", "map(sqrt, 4)", "map(4, [1])", "reduce(max, [])",
+      "range(1, 2, 0)", "range(0, 1e9)[:]", "range(1 km)",
+    ];
+
+    for input in invalid {
+      assert!(eval(&mut VM::default(), input).is_err(), "{}", input);
+    }
+
+    // Nesting is capped so that dropping, printing and comparing deep lists
+    // cannot overflow the stack.
+    let mut vm = VM::default();
+    assert_eq!(eval(&mut vm, "x = []; for i in 1..256 { x = [x] }; [len(str(x)), x == x]").unwrap(), "[512, true]");
+    assert_eq!(eval(&mut vm, "x = [x]").unwrap_err(), "lists cannot nest more than 256 deep");
+    assert_eq!(eval(&mut vm, "y = []; for i in 0..50000 { y = [y] }; 1").unwrap_err(), "lists cannot nest more than 256 deep");
+    assert_eq!(eval(&mut vm, "y = []; for i in 0..50000 { y = map(k => y, [1]) }; 1").unwrap_err(), "map(): lists cannot nest more than 256 deep");
+  }
+
+  #[test]
+  fn matrices() {
+    let testcases = vec![
+      ("[[1, 2], [3, 4]] * [5, 6]", "[17, 39]"),
+      ("[5, 6] * [[1, 2], [3, 4]]", "[23, 34]"),
+      ("[[1, 2], [3, 4]] * [[0, 1], [1, 0]]", "[[2, 1], [4, 3]]"),
+      ("[[1, 2], [3, 4]] + [[1, 1], [1, 1]] - 1", "[[1, 2], [3, 4]]"),
+      ("2 * [[1, 2]] / 4", "[[0.5, 1]]"),
+      ("-[[1, -2]]", "[[-1, 2]]"),
+      ("[[1, 1], [1, 0]] ^ 10", "[[89, 55], [55, 34]]"),
+      ("[[1, 2], [3, 4]][1] + [[1, 2], [3, 4]][0][1]", "[5, 6]"),
+      ("[[1, 2], [3, 4], [5, 6]][1:]", "[[3, 4], [5, 6]]"),
+      ("[[1, 2], [3, 4]] == [[1.0, 2], [3, 4]]", "true"),
+      ("det([[1, 2], [3, 4]])", "-2"),
+      ("inv([[4, 7], [2, 6]])", "[[0.6, -0.7], [-0.2, 0.4]]"),
+      ("inv([[2, 0], [0, 4]]) * [[2, 0], [0, 4]] == identity(2)", "true"),
+      ("transpose([[1, 2, 3], [4, 5, 6]])", "[[1, 4], [2, 5], [3, 6]]"),
+      ("transpose([1, 2])", "[[1], [2]]"),
+      ("solve([[2, 1], [1, 3]], [3, 5])", "[0.8, 1.4]"),
+      ("solve([[1, 0], [0, 2]], [[1, 2], [4, 6]])", "[[1, 2], [2, 3]]"),
+      ("rank([[1, 2], [2, 4]]) + rank(identity(3))", "4"),
+      ("trace([[1, 2], [3, 4]])", "5"),
+      ("map(round, eig([[2, 0, 0], [0, 3, 4], [0, 4, 9]]))", "[1, 2, 11]"),
+      ("len([[1, 2], [3, 4], [5, 6]])", "3"),
+    ];
+
+    for (input, expected) in testcases {
+      assert_eq!(eval(&mut VM::default(), input).unwrap(), expected, "{}", input);
+    }
+
+    let invalid = [
+      "[[1, 2], [3, 4]] * [[1, 2, 3]]", "[[1, 2]] + [[1], [2]]", "[[1, 2], [3, 4]] * [1, 2, 3]", "1 / [[1]]",
+      "det([[1, 2]])", "inv([[1, 2], [2, 4]])", "solve([[1, 0], [0, 1]], [1, 2, 3])", "eig([[1, 2], [3, 4]])",
+      "identity(0)", "det([1, 2])", "[[1, 2]] ^ 2", "len(identity(1000) ^ 1000)",
+    ];
+
+    for input in invalid {
+      assert!(eval(&mut VM::default(), input).is_err(), "{}", input);
+    }
+  }
+
+  #[test]
+  fn complex() {
+    let testcases = vec![
+      ("sqrt(-1)", "i"),
+      ("sqrt(-4) == 2i", "true"),
+      ("(3 + 4i) * (3 - 4i)", "25"),
+      ("(1 + 2i) / (3 - 1i)", "0.1+0.7i"),
+      ("1i ^ 2", "-1"),
+      ("-(2 - 2.5j)", "-2+2.5i"),
+      ("abs(3 + 4i) + re(3 + 4i) + im(3 + 4i)", "12"),
+      ("conj(3 + 4i)", "3-4i"),
+      ("arg(-1 + 0i) == atan2(0, -1)", "true"),
+      ("polar(2, 0)", "2"),
+      ("re(exp(ln(-1)))", "-1"),
+      ("ln(-1) == 3.141592653589793i", "true"),
+    ];
+
+    for (input, expected) in testcases {
+      assert_eq!(eval(&mut VM::default(), input).unwrap(), expected, "{}", input);
+    }
+
+    for input in ["1i < 2", "(1 + 1i) % 2", "1i / 0", "sin(1i)"] {
+      assert!(eval(&mut VM::default(), input).is_err(), "{}", input);
+    }
+  }
+
+  #[test]
+  fn units() {
+    let testcases = vec![
+      ("5 km / 20 min", "0.25 km/min"),
+      ("5 km / 20 min to km/h", "15 km/h"),
+      ("60 mph to m/s", "26.8224 m/s"),
+      ("3 m/s^2 * 2 s", "6 m/s"),
+      ("2 kW * 3 h in kWh", "6 kWh"),
+      ("5 in to cm", "12.7 cm"),
+      ("1 ft + 6 in", "1.5 ft"),
+      ("(2 m) ^ 2", "4 m^2"),
+      ("-5 kg * 2", "-10 kg"),
+      ("1 km / 1 m", "1000"),
+      ("10 m > 1 ft && 1 m == 100 cm", "true"),
+      ("1 m == 1 s", "false"),
+    ];
+
+    for (input, expected) in testcases {
+      assert_eq!(eval(&mut VM::default(), input).unwrap(), expected, "{}", input);
+    }
+
+    for input in ["1 m + 1 s", "1 m < 1 s", "3 to m", "1 m + 1", "2 ^ 1 m", "(1 m) ^ 0.5"] {
+      assert!(eval(&mut VM::default(), input).is_err(), "{}", input);
+    }
+
+    for input in ["2 m / 0", "2 m % 0 m", "2 m / (0 s)", "(0 m) ^ -1"] {
+      assert_eq!(eval(&mut VM::default(), input).unwrap_err(), "division by zero", "{}", input);
+    }
+  }
+
+  #[test]
+  fn functions() {
+    let testcases = vec![
+      ("f(x, y) = x^2 + y", "<fn f>"),
+      ("f(3, 1)", "10"),
+      ("fact(n) = if n <= 1 then 1 else n * fact(n - 1)", "<fn fact>"),
+      ("fact(20)", "2432902008176640000"),
+      ("fib(n) = if n < 2 then n else fib(n - 1) + fib(n - 2)", "<fn fib>"),
+      ("fib(15)", "610"),
+      ("scale = 3", "3"),
+      ("triple(x) = scale * x", "<fn triple>"),
+      ("map(triple, [1, 2])", "[3, 6]"),
+      ("scale = 10", "10"),
+      ("triple(2) + f(1, 0)", "21"),
+      ("g = f", "<fn f>"),
+      ("g == f", "true"),
+      ("h(x) = x + true", "<fn h>"),
+      ("deep(n) = if n <= 0 then 0 else map([n], x => deep(x - 1))[0] + 1", "<fn deep>"),
+      ("deep(90)", "90"),
+    ];
+
+    let mut vm = VM::default();
+
+    for (input, expected) in testcases {
+      assert_eq!(eval(&mut vm, input).unwrap(), expected, "{}", input);
+    }
+
+    let err = eval(&mut vm, "deep(1000)").unwrap_err();
+    assert!(err.ends_with("maximum nesting of 100 calls from natives exceeded"), "{}", err);
+    assert!(err.contains("(deep(): map(): lambda()) ×") && err.len() < 200, "{}", err);
+
+    vm.set_max_call_depth(50);
+
+    assert_eq!(eval(&mut vm, "fact(50) > 0").unwrap(), "true");
+
+    let invalid = vec![
+      ("f(1)", "f() expects 2 arguments, got 1"),
+      ("fact(100)", "fact(): maximum call depth of 50 exceeded"),
+      ("fact(51)", "fact(): maximum call depth of 50 exceeded"),
+      ("1 + h(1)", "h(): invalid operands for '+'"),
+      ("map(h, [1])", "map(): h(): invalid operands for '+'"),
+    ];
+
+    for (input, expected) in invalid {
+      let err = eval(&mut vm, input).unwrap_err();
+      assert!(err.starts_with(expected), "{}: {}", input, err);
+    }
+  }
+
+  #[test]
+  fn closures() {
+    let testcases = vec![
+      ("rate = 2", "2"),
+      ("map([1, 2, 3], x => x * rate)", "[2, 4, 6]"),
+      ("filter(x => x % 2 == 1, [1, 2, 3])", "[1, 3]"),
+      ("reduce([1, 2, 3], (acc, x) => acc + x, 10)", "16"),
+      ("adder(n) = x => x + n", "<fn adder>"),
+      ("add5 = adder(5)", "<fn lambda>"),
+      ("add5(1) + adder(10)(1)", "17"),
+      ("curry(f) = a => b => f(a, b)", "<fn curry>"),
+      ("curry((x, y) => x - y)(10)(3)", "7"),
+      ("compose(f, g) = x => f(g(x))", "<fn compose>"),
+      ("compose(x => x * 2, add5)(1)", "12"),
+      ("(() => 42)()", "42"),
+      ("scale(k) = map([1, 2], x => x * k + rate)", "<fn scale>"),
+      ("scale(3)", "[5, 8]"),
+      ("add5 == add5", "true"),
+      ("adder(5) == adder(5)", "false"),
+    ];
+
+    let mut vm = VM::default();
+
+    for (input, expected) in testcases {
+      assert_eq!(eval(&mut vm, input).unwrap(), expected, "{}", input);
+    }
+
+    let invalid = vec![
+      "add5(1, 2)",
+      "map([1], x => x + true)",
+      "(x => x)()",
+    ];
+
+    for input in invalid {
+      assert!(eval(&mut vm, input).is_err(), "{}", input);
+    }
+  }
+
+  #[test]
+  fn programs() {
+    let testcases = vec![
+      ("a = 1; b = 2; a + b", "3"),
+      ("a = 1\nb = a + 1\n\nb * 10\n", "20"),
+      ("x = [1,\n  2]\nsum(x) +\n  1", "4"),
+      ("1;", "1"),
+      ("let x = 1, y = 2 in x + y", "3"),
+      ("let x = 2, y = x * 3 in y", "6"),
+      ("10 - let x = 1 in let y = x + 1 in x * y", "8"),
+      ("let x = 1 in (let x = 5 in x) + x", "6"),
+      ("let x = 1 in if x > 0 then let y = 2 in x + y else x", "3"),
+      ("let d = 5 km in d to m", "5000 m"),
+      ("let d = (5 in to cm) in d", "12.7 cm"),
+      ("let k = 3 in map([1, 2], x => x * k)", "[3, 6]"),
+      ("f(a) = let b = a * 2 in [a, b]\nf(4)", "[4, 8]"),
+      ("g(a) = let b = a + 1 in x => a + b + x\ng(1)(10)", "13"),
+    ];
+
+    for (input, expected) in testcases {
+      assert_eq!(eval(&mut VM::default(), input).unwrap(), expected, "{}", input);
+    }
+  }
+
+  #[test]
+  fn loops() {
+    let testcases = vec![
+      ("(1..5)[:]", "[1, 2, 3, 4]"),
+      ("total = 0; for i in 1..5 { total = total + i }; total", "10"),
+      ("n = 100; steps = 0\nwhile n > 1 {\n  n = n / 2\n  steps = steps + 1\n}\nsteps", "7"),
+      ("s = \"\"; for ch in \"abc\" { s = ch + s }; s", "cba"),
+      ("t = 0; for row in [[1, 2], [3, 4]] { t = t + row[1] }; t", "6"),
+      ("c = 0; for x in [] { c = c + 1 }; c", "0"),
+      ("for k in 0..3 { g = x => x * k }; g(10)", "20"),
+      ("balance = 1000; for month in 0..12 { balance = balance * 1.01 - 50 }; round(balance)", "493"),
+      ("let n = 3 in n + 1", "4"),
+      ("{ a = 1; a + 1 }", "2"),
+      ("for i in 0..10 { i }", "()"),
+      ("x = 1; for i in 1..3 { x = x * 2 }", "()"),
+      ("while false { 1 }", "()"),
+      ("{}", "()"),
+      ("i = 0; for i in 1..3 { i = 10 }; i", "0"),
+      ("t = 0; for i in 1..4 { i = i * 10; t = t + i }; t", "60"),
+      ("for x in [1] { x = 5; y = x }; y", "5"),
+    ];
+
+    for (input, expected) in testcases {
+      assert_eq!(eval(&mut VM::default(), input).unwrap(), expected, "{}", input);
+    }
+
+    let invalid = vec![
+      ("while true { 1 }", "loop iteration limit of 1000 exceeded"),
+      ("for i in 5 { i }", "cannot iterate over number"),
+    ];
+
+    let mut vm = VM::default();
+    vm.set_max_iterations(1000);
+
+    for (input, expected) in invalid {
+      assert_eq!(eval(&mut vm, input).unwrap_err(), expected, "{}", input);
+    }
+  }
+
+  #[test]
+  fn ranges() {
+    let testcases = vec![
+      ("1..10", "1..10"),
+      ("0..1 step 0.1", "0..1 step 0.1"),
+      ("(0..1 step 0.1)[:3]", "[0, 0.1, 0.2]"),
+      ("len(0..1 step 0.1)", "10"),
+      ("len(1..=10)", "10"),
+      ("(10..=0 step -5)[:]", "[10, 5, 0]"),
+      ("(1..10)[-1]", "9"),
+      ("step = 2; (0..7 step step)[:]", "[0, 2, 4, 6]"),
+      ("sum(1..=100)", "5050"),
+      ("sum(1..=10^9)", "500000000500000000"),
+      ("product(1..=10)", "3628800"),
+      ("mean(1..=4)", "2.5"),
+      ("map(1..4, x => x * x)", "[1, 4, 9]"),
+      ("filter(x => x % 3 == 0, 1..10)", "[3, 6, 9]"),
+      ("reduce(1..5, (a, b) => a * b)", "24"),
+      ("sum(k in 1..=4, k^2)", "30"),
+      ("product(k in 1..=5, k)", "120"),
+      ("n = 3; sum(k in 0..=n, 1 / 2^k)", "1.875"),
+      ("sum(i in 1..=3, sum(j in 1..=i, j))", "10"),
+      ("sum(x in [1, 2], x) + product(c in [], c)", "4"),
+      ("t = 0; for i in 1..=3 { t = t + i }; t", "6"),
+      ("(1..3) == (1..3)", "true"),
+      ("(1..3) == (1..3.0)", "true"),
+      ("(1..=3) == (1..4)", "true"),
+      ("(0..1 step 1/2) == (0..=0.5 step 0.5)", "true"),
+      ("(1..1) == (5..2)", "true"),
+      ("(1..2) == (1..2 step 7)", "true"),
+      ("(1..3) == (1..4)", "false"),
+      ("(1..5 step 2) == (1..5)", "false"),
+      ("len(5..1)", "0"),
+      ("len(0..10^19)", "10000000000000000000"),
+      ("len(0..10^30)", "1000000000000000000000000000000"),
+      ("len(0..=10^20 step 10^19)", "11"),
+      ("sum(0..10^19)", "49999999999999999995000000000000000000"),
+      ("sum(0..10^20)", "4999999999999999999950000000000000000000"),
+      ("(0..10^20)[5]", "5"),
+      ("(0..10^20)[-1]", "99999999999999999999"),
+      ("(0..10^20)[-2:]", "[99999999999999999998, 99999999999999999999]"),
+    ];
+
+    for (input, expected) in testcases {
+      assert_eq!(eval(&mut VM::default(), input).unwrap(), expected, "{}", input);
+    }
+
+    let invalid = vec![
+      "0..5 step 0",
+      "1..true",
+      "(1..3)[5]",
+      "(0..10^20)[10^20]",
+      "(0..10^20)[:]",
+      "0..10^400 step sqrt(2)",
+      "for i in 0..10^30 { i }",
+      "sum(k in 0..10^7, k)",
+      "map(0..10^7, x => x)",
+    ];
+
+    let mut vm = VM::default();
+    vm.set_max_iterations(1000);
+
+    for input in invalid {
+      assert!(eval(&mut vm, input).is_err(), "{}", input);
+    }
+  }
+
+  #[test]
+  fn runtime_error() {
+    match run(&mut VM::default(), "1 + (true + 1)") {
+      Err(Error::Runtime(_, span)) => assert_eq!((span.start, span.end), (4, 14)),
+      result => panic!("expected runtime error, got {:?}", result),
     }
   }
 }
\ No newline at end of file